@@ -0,0 +1,124 @@
+#[macro_use]
+extern crate criterion;
+
+use criterion::Criterion;
+
+use saito_core::blockchain::BlockchainIndex;
+use saito_primitives::block::BlockHeader;
+use saito_primitives::burnfee::BurnFee;
+
+const CHAIN_LENGTH: u32 = 100_000;
+const FORK_DEPTH: u32 = 10;
+
+fn create_bsh(bid: u32, fork: u8) -> [u8; 32] {
+    let mut bsh: [u8; 32] = [0; 32];
+    bsh[0] = fork + 1;
+    bsh[28..32].copy_from_slice(&bid.to_be_bytes());
+    return bsh;
+}
+
+fn create_block_header(bid: u32, bsh: [u8; 32], prevbsh: [u8; 32]) -> BlockHeader {
+    return BlockHeader::new(
+        bsh,
+        prevbsh,
+        bid,
+        bid as u64,
+        BurnFee::new(1.0, 1),
+        0,
+        0,
+        1.0,
+        0.5,
+        0,
+        0,
+        0,
+        0,
+    );
+}
+
+//
+// a longest chain of CHAIN_LENGTH blocks with a competing fork
+// branching off FORK_DEPTH blocks before the tip
+//
+fn create_block_headers() -> (Vec<BlockHeader>, [u8; 32], [u8; 32]) {
+    let mut block_headers: Vec<BlockHeader> = vec![];
+
+    let mut prevbsh: [u8; 32] = [0; 32];
+    for bid in 1..=CHAIN_LENGTH {
+        let bsh = create_bsh(bid, 0);
+        block_headers.push(create_block_header(bid, bsh, prevbsh));
+        prevbsh = bsh;
+    }
+    let lc_bsh = prevbsh;
+
+    let mut prevbsh = create_bsh(CHAIN_LENGTH - FORK_DEPTH, 0);
+    for bid in (CHAIN_LENGTH - FORK_DEPTH + 1)..=(CHAIN_LENGTH + 1) {
+        let bsh = create_bsh(bid, 1);
+        block_headers.push(create_block_header(bid, bsh, prevbsh));
+        prevbsh = bsh;
+    }
+    let fork_bsh = prevbsh;
+
+    return (block_headers, lc_bsh, fork_bsh);
+}
+
+//
+// the shared ancestor search the blockchain used before the index
+// became a tree: walk the list of headers backwards position by
+// position, following each chain's prevbsh as we pass it
+//
+fn linear_common_ancestor(block_headers: &Vec<BlockHeader>, bsh1: [u8; 32], bsh2: [u8; 32]) -> Option<[u8; 32]> {
+    let mut prevbsh1 = bsh1;
+    let mut prevbsh2 = bsh2;
+
+    for block_header in block_headers.iter().rev() {
+        if block_header.bsh == prevbsh1 && block_header.bsh == prevbsh2 {
+            return Some(block_header.bsh);
+        }
+        if block_header.bsh == prevbsh1 { prevbsh1 = block_header.prevbsh; }
+        if block_header.bsh == prevbsh2 { prevbsh2 = block_header.prevbsh; }
+    }
+
+    return None;
+}
+
+fn create_index(block_headers: &Vec<BlockHeader>) -> BlockchainIndex {
+    let mut index = BlockchainIndex::new();
+    for block_header in block_headers.iter() {
+        index.insert(block_header.clone());
+    }
+    return index;
+}
+
+fn common_ancestor_benchmark(c: &mut Criterion) {
+    let (block_headers, lc_bsh, fork_bsh) = create_block_headers();
+    let index = create_index(&block_headers);
+
+    c.bench_function("common ancestor -- linear search at 100k blocks", move |b| {
+        b.iter(|| linear_common_ancestor(&block_headers, lc_bsh, fork_bsh))
+    });
+
+    c.bench_function("common ancestor -- block tree at 100k blocks", move |b| {
+        b.iter(|| index.return_common_ancestor(lc_bsh, fork_bsh).map(|block_node| block_node.height))
+    });
+}
+
+//
+// looking up the parent of a block deep in the chain, which is
+// what is_bsh_indexed and the ancestor search need on every block
+//
+fn header_lookup_benchmark(c: &mut Criterion) {
+    let (block_headers, _, _) = create_block_headers();
+    let index = create_index(&block_headers);
+    let bsh = create_bsh(1_000, 0);
+
+    c.bench_function("header lookup -- linear search at 100k blocks", move |b| {
+        b.iter(|| block_headers.iter().find(|block_header| block_header.bsh == bsh).map(|block_header| block_header.bid))
+    });
+
+    c.bench_function("header lookup -- block tree at 100k blocks", move |b| {
+        b.iter(|| index.get(&bsh).map(|block_node| block_node.header.bid))
+    });
+}
+
+criterion_group!(benches, common_ancestor_benchmark, header_lookup_benchmark);
+criterion_main!(benches);
@@ -158,47 +158,28 @@ impl Block {
         self.body.txs.push(tx);
     }
 
+    //
+    // inputs are left as they were signed, keeping the identity
+    // of the output they spend
+    //
     pub fn set_transactions(&mut self, transactions: &mut Vec<Transaction>) {
         std::mem::swap(&mut self.body.txs, transactions);
 
         let tx_length = self.body.txs.len();
         let maxtid = self.maxtid;
-        let bid = self.body.id;
-        let bsh = self.return_bsh();
 
         // used for calculating cumulative fees
         let mut cumulative_fees = 0;
 
         for (i, tx) in self.body.txs.iter_mut().enumerate() {
             let current_tid = maxtid + i as u32 + 1;
-            let mut current_sid = 0;
-            
+
             // set tx id
             tx.set_id(current_tid);
 
-            let to_slips = tx.return_to_slips()
-                .iter_mut()
-                .map(move |slip| {
-                    slip.set_ids(0, 0, current_sid);
-                    current_sid += 1;
-                    return slip.clone();
-                })
-                .collect();
-            
-            tx.set_to_slips(to_slips);
-            
-            let from_slips = tx.return_from_slips()
-                .iter_mut()
-                .map(move |slip| {
-                    slip.set_ids(bid, current_tid, current_sid);
-                    slip.set_bsh(bsh);
-                    current_sid += 1;
-                    return slip.clone();
-                })
-                .collect();
-            
-            tx.set_from_slips(from_slips);
-            
+            // give each output its identity
+            tx.set_output_ids();
+
             // calculate cumulative fees 
             cumulative_fees = tx.calculate_cumulative_fees(cumulative_fees);
         }
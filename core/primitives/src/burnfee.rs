@@ -44,6 +44,33 @@ impl BurnFee {
 	return work_needed.round() as u64;
     }
 
+    /// returns how many milliseconds after `ts` the work needed to produce a
+    /// block falls to `work_available`, or 0 if it already has
+    ///
+    /// * `prevts`         - timestamp of previous block
+    /// * `ts`             - current timestamp
+    /// * `start`          - burn fee value (y-axis) for curve determination ("start")
+    /// * `work_available` - routing work we can put towards the block
+    ///
+    pub fn return_time_until_work_needed(prevts: u64, ts: u64, start: f32, work_available: u64) -> u64 {
+        let max_elapsed_time = 2 * HEARTBEAT + 1;
+
+        let mut elapsed_time = match work_available {
+            0 => max_elapsed_time,
+            _ => ((start as f64 * 100_000_000.0) / work_available as f64).ceil() as u64,
+        };
+        if elapsed_time > max_elapsed_time { elapsed_time = max_elapsed_time; }
+
+        // step past any rounding in the curve
+        while BurnFee::return_work_needed(prevts, prevts + elapsed_time, start) > work_available {
+            elapsed_time += 1;
+        }
+
+        let block_ts = prevts + elapsed_time;
+        if block_ts <= ts { return 0; }
+        return block_ts - ts;
+    }
+
     pub fn adjust_work_needed(previous_block_header: BlockHeader, current_block_timestamp: u64) -> Self {
         let start: f32 = BurnFee::burn_fee_adjustment(previous_block_header.clone(), current_block_timestamp); 
         let current: u64 = BurnFee::return_work_needed(previous_block_header.ts, current_block_timestamp, previous_block_header.bf.start);
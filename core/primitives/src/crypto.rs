@@ -36,4 +36,10 @@ pub fn sign(data: &[u8; 32], privatekey: &SecretKey) -> Signature {
     return sign.sign(&msg, privatekey)
 }
 
+pub fn verify(data: &[u8; 32], sig: &Signature, publickey: &PublicKey) -> bool {
+    let verify = Secp256k1::verification_only();
+    let msg = Message::from_slice(data).unwrap();
+    return verify.verify(&msg, sig, publickey).is_ok();
+}
+
 
@@ -17,6 +17,10 @@ impl GoldenTicket {
         return GoldenTicket{vote, target, random, publickey};
     }
 
+    pub fn return_target(&self) -> [u8; 32] {
+        return self.target;
+    }
+
     pub fn calculate_difficulty (&self, previous_difficulty: f32) -> f32 {
         return match self.vote {
             1 => previous_difficulty + 0.01,
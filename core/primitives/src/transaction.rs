@@ -2,7 +2,7 @@ use serde::{Serialize, Deserialize};
 use crate::hop::{Hop};
 use crate::slip::{Slip};
 use crate::helper::{create_timestamp};
-use crate::crypto::{Signature, PublicKey};
+use crate::crypto::{Signature, PublicKey, hash, verify};
 
 #[derive(Serialize, Deserialize, PartialEq, Debug, Copy, Clone)]
 pub enum TransactionBroadcastType {
@@ -67,6 +67,21 @@ impl Transaction {
         self.body.to.push(slip);
     }
 
+    //
+    // an output is identified by the hash of the transaction that
+    // creates it and its sid. the hash leaves these ids out, so the
+    // identity is the same whether it is given while the transaction
+    // is pending or when it is bundled, and equal payments made by
+    // different transactions never share one
+    //
+    pub fn set_output_ids(&mut self) {
+        let tx_hash = self.return_hash();
+        for (sid, slip) in self.body.to.iter_mut().enumerate() {
+            slip.set_ids(0, 0, sid as u32);
+            slip.set_bsh(tx_hash);
+        }
+    }
+
     pub fn add_from_slip(&mut self, slip: Slip) {
         self.body.from.push(slip)
     }
@@ -154,6 +169,14 @@ impl Transaction {
     pub fn set_id(&mut self, id: u32) {
         self.body.id = id;
     }
+
+    pub fn return_ts(&self) -> u64 {
+        return self.body.ts;
+    }
+
+    pub fn set_ts(&mut self, ts: u64) {
+        self.body.ts = ts;
+    }
     
     pub fn return_tx_type(&self) -> TransactionBroadcastType {
         return self.body.typ;
@@ -170,14 +193,41 @@ impl Transaction {
         return 100_000;
     }
 
+    //
+    // the signed payload leaves out the signature itself, the id
+    // the transaction is given when it is put into a block and the
+    // identity of its outputs, so it is the same before and after
+    // the transaction is bundled
+    //
     pub fn return_signature_source(&self) -> Vec<u8> {
-        return bincode::serialize(&self.body).unwrap();
+        let mut unsigned_body = self.body.clone();
+        unsigned_body.id  = 0;
+        unsigned_body.sig = Signature::from_compact(&[0; 64]).unwrap();
+        for slip in unsigned_body.to.iter_mut() {
+            slip.set_ids(0, 0, 0);
+            slip.set_bsh([0; 32]);
+        }
+        return bincode::serialize(&unsigned_body).unwrap();
+    }
+
+    //
+    // the hash of the signed payload, which identifies the
+    // transaction wherever it is seen
+    //
+    pub fn return_hash(&self) -> [u8; 32] {
+        let mut output: [u8; 32] = [0; 32];
+        hash(self.return_signature_source(), &mut output);
+        return output;
     }
 
     pub fn set_sig(&mut self, sig: Signature) {
         self.body.sig = sig 
     }
 
+    pub fn verify_signature(&self, publickey: &PublicKey) -> bool {
+        return verify(&self.return_hash(), &self.body.sig, publickey);
+    }
+
     pub fn calculate_cumulative_fees(&mut self, last_fees: u64) -> u64 {
         let total_fees = self.return_fees_total();
         let mut cumulative_fees = 0;
@@ -0,0 +1,142 @@
+use serde::{Serialize, Deserialize};
+
+use saito_primitives::block::{Block, BlockHeader};
+use saito_primitives::crypto::PublicKey;
+use saito_primitives::slip::{Slip, SlipSpentStatus};
+
+use crate::blockchain::ChainReorganization;
+use crate::storage::{Storage, StorageError};
+
+//
+// a slip sent to an address, the block it was confirmed in and
+// whether it has since been spent on the longest chain
+//
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct AddressSlip {
+    pub slip:         Slip,
+    pub bid:          u32,
+    pub spent_status: SlipSpentStatus,
+}
+
+//
+// what the index holds for one address
+//
+#[derive(PartialEq, Debug, Clone)]
+pub struct AddressInfo {
+    pub balance: u64,
+    pub history: Vec<AddressSlip>,
+}
+
+//
+// Address Index
+//
+// keeps every slip on the longest chain in storage, keyed by
+// the public key it was sent to, so that the balance and history
+// of any address can be looked up without rescanning blocks.
+//
+// the index is optional. it is kept up to date by registering it
+// with the blockchain, which winds and unwinds it along with the
+// longest chain.
+//
+pub struct AddressIndex {
+    storage: Storage,
+}
+
+impl AddressIndex {
+    pub fn new(storage: Storage) -> AddressIndex {
+        return AddressIndex {
+            storage,
+        };
+    }
+
+    pub fn return_balance(&self, publickey: &PublicKey) -> Result<u64, StorageError> {
+        return Ok(self.return_unspent_slips(publickey)?
+            .iter()
+            .map(|address_slip| address_slip.slip.return_amt())
+            .sum());
+    }
+
+    pub fn return_unspent_slips(&self, publickey: &PublicKey) -> Result<Vec<AddressSlip>, StorageError> {
+        return Ok(self.return_history(publickey)?
+            .into_iter()
+            .filter(|address_slip| address_slip.spent_status == SlipSpentStatus::Unspent)
+            .collect());
+    }
+
+    //
+    // every slip sent to this address, oldest first
+    //
+    pub fn return_history(&self, publickey: &PublicKey) -> Result<Vec<AddressSlip>, StorageError> {
+        let mut address_slips = self.storage.read_address_slips(publickey)?;
+        address_slips.sort_by_key(|address_slip| address_slip.bid);
+        return Ok(address_slips);
+    }
+
+    pub fn return_info(&self, publickey: &PublicKey) -> Result<AddressInfo, StorageError> {
+        let history = self.return_history(publickey)?;
+        let balance = history
+            .iter()
+            .filter(|address_slip| address_slip.spent_status == SlipSpentStatus::Unspent)
+            .map(|address_slip| address_slip.slip.return_amt())
+            .sum();
+
+        return Ok(AddressInfo { balance, history });
+    }
+
+    fn wind_block(&self, blk: &Block) -> Result<(), StorageError> {
+        for tx in blk.body.txs.iter() {
+            for slip in tx.return_to_slips().into_iter() {
+                self.storage.write_address_slip(&AddressSlip {
+                    slip,
+                    bid: blk.body.id,
+                    spent_status: SlipSpentStatus::Unspent,
+                })?;
+            }
+            for slip in tx.return_from_slips().iter() {
+                self.set_spent_status(slip, SlipSpentStatus::Spent)?;
+            }
+        }
+        return Ok(());
+    }
+
+    //
+    // undo wind_block, taking the transactions in reverse so that
+    // slips created and spent in the same block are handled
+    //
+    fn unwind_block(&self, blk: &Block) -> Result<(), StorageError> {
+        for tx in blk.body.txs.iter().rev() {
+            for slip in tx.return_from_slips().iter() {
+                self.set_spent_status(slip, SlipSpentStatus::Unspent)?;
+            }
+            for slip in tx.return_to_slips().iter() {
+                self.storage.remove_address_slip(&slip.return_add(), &slip.return_signature_source())?;
+            }
+        }
+        return Ok(());
+    }
+
+    //
+    // inputs that never reached this index are ignored
+    //
+    fn set_spent_status(&self, slip: &Slip, spent_status: SlipSpentStatus) -> Result<(), StorageError> {
+        if let Some(mut address_slip) = self.storage.read_address_slip(&slip.return_add(), &slip.return_signature_source())? {
+            address_slip.spent_status = spent_status;
+            self.storage.write_address_slip(&address_slip)?;
+        }
+        return Ok(());
+    }
+}
+
+impl ChainReorganization for AddressIndex {
+    fn on_chain_reorganization(&mut self, _block_header: &BlockHeader, blk: &Block, longest_chain: bool) {
+        let result = if longest_chain {
+            self.wind_block(blk)
+        } else {
+            self.unwind_block(blk)
+        };
+
+        if let Err(err) = result {
+            println!("address index -- could not update slips for block {}: {}", blk.return_bsh_as_hex(), err);
+        }
+    }
+}
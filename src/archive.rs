@@ -0,0 +1,187 @@
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, prelude::*};
+use std::path::Path;
+
+use saito_primitives::block::Block;
+
+use crate::block_file::{encode_block_file, decode_block_file};
+use crate::blockchain::Blockchain;
+use crate::shashmap::Shashmap;
+use crate::storage::{Storage, StorageError};
+
+//
+// Chain Archive
+//
+// a bootstrap archive holds the longest chain in order, from the
+// earliest block we have up to the tip:
+//
+//   magic           4 bytes    "SAIA"
+//   format version  2 bytes    little endian
+//
+// followed by one record per block:
+//
+//   record length   8 bytes    little endian
+//   block           the block in the block file format
+//
+// the block body carries every header field, so headers are not
+// stored separately. each record is checksummed by the block file
+// format.
+//
+pub const ARCHIVE_MAGIC: [u8; 4] = *b"SAIA";
+pub const ARCHIVE_VERSION: u16 = 1;
+
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub struct ArchiveProgress {
+    pub blocks:      usize,	// blocks written or read so far
+    pub skipped:     usize,	// blocks already in our chain
+    pub bytes:       u64,
+    pub total_bytes: u64,
+}
+
+pub fn export_chain(
+    blockchain: &Blockchain,
+    storage: &Storage,
+    path: &Path,
+    progress: &mut dyn FnMut(&ArchiveProgress),
+) -> Result<ArchiveProgress, StorageError> {
+    let longest_chain_hashes: Vec<[u8; 32]> = blockchain.longest_chain_iter().map(|block_header| block_header.bsh).collect();
+
+    let mut w = BufWriter::new(File::create(path)?);
+    w.write_all(&ARCHIVE_MAGIC)?;
+    w.write_all(&ARCHIVE_VERSION.to_le_bytes())?;
+
+    let mut archive_progress = ArchiveProgress::default();
+    archive_progress.bytes = 6;
+
+    for bsh in longest_chain_hashes.iter() {
+        let blk = storage.read_block_from_disk(*bsh)?;
+        let encoded = encode_block_file(blk.return_body()).map_err(|err| StorageError::CorruptBlock(*bsh, err))?;
+
+        w.write_all(&(encoded.len() as u64).to_le_bytes())?;
+        w.write_all(&encoded[..])?;
+
+        archive_progress.blocks += 1;
+        archive_progress.bytes += 8 + encoded.len() as u64;
+        archive_progress.total_bytes = archive_progress.bytes;
+        progress(&archive_progress);
+    }
+
+    w.flush()?;
+    w.get_ref().sync_all()?;
+
+    return Ok(archive_progress);
+}
+
+//
+// every block goes through add_block, so it is validated just as
+// it would be if it came from the network. blocks that are already
+// in our chain are skipped, which lets an interrupted import pick
+// up where it left off. add_block saves every block along with the
+// chain state, so nothing is lost if the import is interrupted.
+//
+pub fn import_chain(
+    blockchain: &mut Blockchain,
+    shashmap: &mut Shashmap,
+    storage: &Storage,
+    path: &Path,
+    progress: &mut dyn FnMut(&ArchiveProgress),
+) -> Result<ArchiveProgress, StorageError> {
+    let file = File::open(path)?;
+
+    let mut archive_progress = ArchiveProgress::default();
+    archive_progress.total_bytes = file.metadata()?.len();
+
+    let mut r = BufReader::new(file);
+
+    let mut archive_header: [u8; 6] = [0; 6];
+    r.read_exact(&mut archive_header)?;
+    if archive_header[0..4] != ARCHIVE_MAGIC {
+        return Err(StorageError::InvalidArchive(String::from("not a chain archive")));
+    }
+    let version = u16::from_le_bytes(archive_header[4..6].try_into().unwrap());
+    if version != ARCHIVE_VERSION {
+        return Err(StorageError::InvalidArchive(format!("unsupported archive version {}", version)));
+    }
+    archive_progress.bytes = 6;
+
+    loop {
+        let mut record_length: [u8; 8] = [0; 8];
+        match r.read_exact(&mut record_length) {
+            Ok(()) => {},
+            Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(err) => return Err(StorageError::Io(err)),
+        }
+
+        let mut encoded = vec![0; u64::from_le_bytes(record_length) as usize];
+        r.read_exact(&mut encoded[..])
+            .map_err(|_| StorageError::InvalidArchive(String::from("archive ends in the middle of a block")))?;
+
+        let body = decode_block_file(&encoded[..])
+            .map_err(|err| StorageError::InvalidArchive(format!("block {} is corrupt: {}", archive_progress.blocks + 1, err)))?;
+        let blk = Block::create_from_block_body(body);
+        let bsh = blk.return_bsh();
+
+        if blockchain.is_bsh_indexed(bsh) {
+            archive_progress.skipped += 1;
+        } else {
+            blockchain.add_block(blk, shashmap, storage);
+            if !blockchain.is_on_longest_chain(bsh) {
+                return Err(StorageError::InvalidArchive(format!("block {} was rejected", archive_progress.blocks + 1)));
+            }
+        }
+
+        archive_progress.blocks += 1;
+        archive_progress.bytes += 8 + encoded.len() as u64;
+        progress(&archive_progress);
+    }
+
+    return Ok(archive_progress);
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use saito_primitives::crypto::generate_keys;
+    use crate::storage::TempPath;
+
+    #[test]
+    fn test_export_and_import_chain() {
+        let (_, publickey) = generate_keys();
+
+        let mut blockchain = Blockchain::new();
+        let mut shashmap = Shashmap::new();
+        let storage = Storage::in_memory();
+
+        let mut prevbsh = [0; 32];
+        for bid in 1..=5 {
+            let mut blk = Block::new(publickey, prevbsh);
+            blk.body.id = bid;
+            prevbsh = blk.return_bsh();
+            blockchain.add_block(blk, &mut shashmap, &storage);
+        }
+
+        let archive = TempPath::new("test_export_and_import_chain");
+        let path = &archive.path;
+        let exported = export_chain(&blockchain, &storage, path, &mut |_| {}).unwrap();
+        assert_eq!(exported.blocks, 5);
+
+        let mut imported_blockchain = Blockchain::new();
+        let mut imported_shashmap = Shashmap::new();
+        let imported_storage = Storage::in_memory();
+
+        let mut reported_blocks: Vec<usize> = vec![];
+        let imported = import_chain(&mut imported_blockchain, &mut imported_shashmap, &imported_storage, path, &mut |archive_progress| {
+            reported_blocks.push(archive_progress.blocks);
+        }).unwrap();
+
+        assert_eq!(imported.blocks, 5);
+        assert_eq!(reported_blocks, vec![1, 2, 3, 4, 5]);
+        assert_eq!(imported_blockchain.return_latest_block_header(), blockchain.return_latest_block_header());
+
+        // importing again resumes past the blocks we already have
+        let resumed = import_chain(&mut imported_blockchain, &mut imported_shashmap, &imported_storage, path, &mut |_| {}).unwrap();
+        assert_eq!(resumed.skipped, 5);
+    }
+}
@@ -0,0 +1,102 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use saito_primitives::block::Block;
+
+pub const DEFAULT_BLOCK_CACHE_SIZE: usize = 100;
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct BlockCacheStats {
+    pub hits:   u64,
+    pub misses: u64,
+    pub len:    usize,
+}
+
+//
+// Block Cache
+//
+// a bounded cache of decoded blocks keyed by bsh. every access
+// stamps the block with a tick, and once the cache is full the
+// block with the oldest tick is evicted.
+//
+pub struct BlockCache {
+    capacity: usize,
+    blocks:   HashMap<[u8; 32], (Arc<Block>, u64)>,	// block and tick of last use
+    ticks:    BTreeMap<u64, [u8; 32]>,			// bsh by tick of last use
+    tick:     u64,
+    hits:     u64,
+    misses:   u64,
+}
+
+impl BlockCache {
+    pub fn new(capacity: usize) -> BlockCache {
+        return BlockCache {
+            capacity,
+            blocks: HashMap::new(),
+            ticks:  BTreeMap::new(),
+            tick:   0,
+            hits:   0,
+            misses: 0,
+        };
+    }
+
+    pub fn get(&mut self, bsh: &[u8; 32]) -> Option<Arc<Block>> {
+        let blk = match self.blocks.get(bsh) {
+            Some((blk, _)) => blk.clone(),
+            None => {
+                self.misses += 1;
+                return None;
+            }
+        };
+
+        self.hits += 1;
+        self.touch(*bsh, blk.clone());
+        return Some(blk);
+    }
+
+    pub fn insert(&mut self, blk: Arc<Block>) {
+        if self.capacity == 0 { return; }
+
+        self.touch(blk.return_bsh(), blk);
+
+        self.evict_oldest_blocks();
+    }
+
+    pub fn remove(&mut self, bsh: &[u8; 32]) {
+        if let Some((_, tick)) = self.blocks.remove(bsh) {
+            self.ticks.remove(&tick);
+        }
+    }
+
+    //
+    // shrinking the cache evicts the least recently used blocks
+    //
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict_oldest_blocks();
+    }
+
+    pub fn return_stats(&self) -> BlockCacheStats {
+        return BlockCacheStats {
+            hits:   self.hits,
+            misses: self.misses,
+            len:    self.blocks.len(),
+        };
+    }
+
+    fn evict_oldest_blocks(&mut self) {
+        while self.blocks.len() > self.capacity {
+            let oldest_tick = *self.ticks.keys().next().unwrap();
+            let oldest_bsh = self.ticks.remove(&oldest_tick).unwrap();
+            self.blocks.remove(&oldest_bsh);
+        }
+    }
+
+    fn touch(&mut self, bsh: [u8; 32], blk: Arc<Block>) {
+        self.tick += 1;
+        if let Some((_, previous_tick)) = self.blocks.insert(bsh, (blk, self.tick)) {
+            self.ticks.remove(&previous_tick);
+        }
+        self.ticks.insert(self.tick, bsh);
+    }
+}
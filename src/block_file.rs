@@ -0,0 +1,146 @@
+use std::convert::TryInto;
+use std::fmt;
+use std::error::Error;
+
+use saito_primitives::block::BlockBody;
+use saito_primitives::crypto::hash;
+
+//
+// Block File Format
+//
+// every block we write to disk is prefixed with a header that
+// lets us recognize the file and check that it is complete:
+//
+//   magic           4 bytes    "SAIB"
+//   format version  2 bytes    little endian
+//   body length     8 bytes    little endian
+//   checksum       32 bytes    SHA-256 of the body
+//
+// followed by the bincode of the BlockBody. block files written
+// before this format existed have no header at all.
+//
+pub const BLOCK_FILE_MAGIC: [u8; 4] = *b"SAIB";
+pub const BLOCK_FILE_VERSION: u16 = 1;
+pub const BLOCK_FILE_HEADER_SIZE: usize = 46;
+
+#[derive(Debug)]
+pub enum BlockFileError {
+    BadMagic,
+    UnsupportedVersion(u16),
+    Truncated { expected: u64, found: u64 },
+    ChecksumMismatch,
+    Serialization(bincode::Error),
+}
+
+impl fmt::Display for BlockFileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BlockFileError::BadMagic => write!(f, "not a block file"),
+            BlockFileError::UnsupportedVersion(version) => write!(f, "unsupported block file version {}", version),
+            BlockFileError::Truncated { expected, found } => write!(f, "truncated block file, expected {} bytes and found {}", expected, found),
+            BlockFileError::ChecksumMismatch => write!(f, "block file checksum does not match"),
+            BlockFileError::Serialization(err) => write!(f, "could not decode block body: {}", err),
+        }
+    }
+}
+
+impl Error for BlockFileError {}
+
+impl From<bincode::Error> for BlockFileError {
+    fn from(err: bincode::Error) -> BlockFileError {
+        return BlockFileError::Serialization(err);
+    }
+}
+
+pub fn is_block_file(encoded: &[u8]) -> bool {
+    return encoded.len() >= BLOCK_FILE_MAGIC.len() && encoded[..BLOCK_FILE_MAGIC.len()] == BLOCK_FILE_MAGIC;
+}
+
+pub fn encode_block_file(body: &BlockBody) -> Result<Vec<u8>, BlockFileError> {
+    let encoded_body = bincode::serialize(body)?;
+
+    let mut checksum: [u8; 32] = [0; 32];
+    hash(encoded_body.clone(), &mut checksum);
+
+    let mut encoded: Vec<u8> = Vec::with_capacity(BLOCK_FILE_HEADER_SIZE + encoded_body.len());
+    encoded.extend_from_slice(&BLOCK_FILE_MAGIC);
+    encoded.extend_from_slice(&BLOCK_FILE_VERSION.to_le_bytes());
+    encoded.extend_from_slice(&(encoded_body.len() as u64).to_le_bytes());
+    encoded.extend_from_slice(&checksum);
+    encoded.extend_from_slice(&encoded_body);
+
+    return Ok(encoded);
+}
+
+//
+// check the header, length and checksum before decoding the body
+//
+pub fn decode_block_file(encoded: &[u8]) -> Result<BlockBody, BlockFileError> {
+    if !is_block_file(encoded) {
+        return Err(BlockFileError::BadMagic);
+    }
+
+    if encoded.len() < BLOCK_FILE_HEADER_SIZE {
+        return Err(BlockFileError::Truncated {
+            expected: BLOCK_FILE_HEADER_SIZE as u64,
+            found: encoded.len() as u64,
+        });
+    }
+
+    let version = u16::from_le_bytes(encoded[4..6].try_into().unwrap());
+    if version != BLOCK_FILE_VERSION {
+        return Err(BlockFileError::UnsupportedVersion(version));
+    }
+
+    let body_length = u64::from_le_bytes(encoded[6..14].try_into().unwrap());
+    let encoded_body = &encoded[BLOCK_FILE_HEADER_SIZE..];
+    if encoded_body.len() as u64 != body_length {
+        return Err(BlockFileError::Truncated {
+            expected: body_length,
+            found: encoded_body.len() as u64,
+        });
+    }
+
+    let mut checksum: [u8; 32] = [0; 32];
+    hash(encoded_body.to_vec(), &mut checksum);
+    if checksum[..] != encoded[14..BLOCK_FILE_HEADER_SIZE] {
+        return Err(BlockFileError::ChecksumMismatch);
+    }
+
+    return Ok(bincode::deserialize(encoded_body)?);
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use saito_primitives::block::Block;
+    use saito_primitives::crypto::generate_keys;
+
+    #[test]
+    fn test_block_file_roundtrip_and_corruption() {
+        let (_, publickey) = generate_keys();
+        let blk = Block::new(publickey, [0; 32]);
+
+        let encoded = encode_block_file(blk.return_body()).unwrap();
+        assert_eq!(decode_block_file(&encoded).unwrap(), *blk.return_body());
+
+        match decode_block_file(&encoded[..encoded.len() - 1]) {
+            Err(BlockFileError::Truncated { .. }) => {},
+            other => panic!("expected a truncated block file, got {:?}", other),
+        }
+
+        let mut corrupted = encoded.clone();
+        let last = corrupted.len() - 1;
+        corrupted[last] ^= 0xff;
+        match decode_block_file(&corrupted) {
+            Err(BlockFileError::ChecksumMismatch) => {},
+            other => panic!("expected a checksum mismatch, got {:?}", other),
+        }
+
+        match decode_block_file(&encoded[BLOCK_FILE_HEADER_SIZE..]) {
+            Err(BlockFileError::BadMagic) => {},
+            other => panic!("expected a bad magic error, got {:?}", other),
+        }
+    }
+}
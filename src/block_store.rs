@@ -0,0 +1,276 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, prelude::*};
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+
+use data_encoding::HEXLOWER;
+use serde::{Serialize, Deserialize};
+
+use saito_primitives::block::{Block, BlockBody};
+
+use crate::block_file::{BlockFileError, encode_block_file, decode_block_file, is_block_file};
+use crate::storage::{StorageError, StorageReport};
+use crate::store::{Store, StoreBatch, COL_BLOCK_BODY};
+
+//
+// Block Store
+//
+// where full blocks are kept. Storage sits in front of a block
+// store and adds the block cache and the indexes, so the chain
+// does not care which of these it is running on.
+//
+// a block store that keeps blocks in the RocksDB store adds them
+// to the batch it is given, so they are committed together with
+// the chain state saved for them. the others write them out
+// straight away, before the batch is committed.
+//
+pub trait BlockStore: Send + Sync {
+    fn write_block(&self, blk: &Block, batch: &mut StoreBatch) -> Result<(), StorageError>;
+    fn read_block(&self, bsh: [u8; 32]) -> Result<Block, StorageError>;
+
+    //
+    // check every block we hold against its block file header
+    //
+    fn verify_blocks(&self, report: &mut StorageReport) -> Result<(), StorageError>;
+
+    //
+    // clean up anything left behind by a write that was cut short
+    //
+    fn remove_incomplete_writes(&self) -> Result<(), StorageError> {
+        return Ok(());
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+pub enum BlockStoreBackend {
+    RocksDb,
+    File,
+}
+
+impl Default for BlockStoreBackend {
+    fn default() -> Self {
+        BlockStoreBackend::RocksDb
+    }
+}
+
+fn encode_block(blk: &Block) -> Result<Vec<u8>, StorageError> {
+    let bsh = blk.return_bsh();
+    return encode_block_file(blk.return_body()).map_err(|err| StorageError::CorruptBlock(bsh, err));
+}
+
+fn decode_block(bsh: [u8; 32], encoded: &[u8]) -> Result<Block, StorageError> {
+    let body = decode_block_file(encoded).map_err(|err| StorageError::CorruptBlock(bsh, err))?;
+    return Ok(Block::create_from_block_body(body));
+}
+
+
+//
+// one .sai file per block in the blocks directory
+//
+// blocks are written to a temporary file which is synced and then
+// renamed into place, so a block file is either complete or not
+// there at all.
+//
+pub struct FileBlockStore {
+    blocks_dir: PathBuf,
+}
+
+impl FileBlockStore {
+    pub fn new(blocks_dir: PathBuf) -> FileBlockStore {
+        return FileBlockStore {
+            blocks_dir,
+        };
+    }
+
+    fn return_block_filename(&self, bsh: [u8; 32]) -> PathBuf {
+        let mut filename = HEXLOWER.encode(&bsh);
+        filename.push_str(&".sai");
+        return self.blocks_dir.join(filename);
+    }
+
+    fn return_temp_block_filename(&self, bsh: [u8; 32]) -> PathBuf {
+        let mut filename = HEXLOWER.encode(&bsh);
+        filename.push_str(&".sai.tmp");
+        return self.blocks_dir.join(filename);
+    }
+}
+
+impl BlockStore for FileBlockStore {
+    fn write_block(&self, blk: &Block, _batch: &mut StoreBatch) -> Result<(), StorageError> {
+        let bsh = blk.return_bsh();
+        let encoded = encode_block(blk)?;
+
+        let temp_filename = self.return_temp_block_filename(bsh);
+        let mut w = File::create(&temp_filename)?;
+        w.write_all(&encoded[..])?;
+        w.sync_all()?;
+
+        fs::rename(&temp_filename, self.return_block_filename(bsh))?;
+
+        //
+        // sync the directory so the rename itself survives a crash
+        //
+        File::open(&self.blocks_dir)?.sync_all()?;
+
+        return Ok(());
+    }
+
+    //
+    // files without a block file header were written before the
+    // format existed and are read as they are
+    //
+    fn read_block(&self, bsh: [u8; 32]) -> Result<Block, StorageError> {
+        let mut encoded = Vec::<u8>::new();
+
+        let mut r = match File::open(self.return_block_filename(bsh)) {
+            Ok(r) => r,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => {
+                return Err(StorageError::BlockNotFound(bsh));
+            },
+            Err(err) => return Err(StorageError::Io(err)),
+        };
+
+        r.read_to_end(&mut encoded)?;
+        if is_block_file(&encoded[..]) {
+            return decode_block(bsh, &encoded[..]);
+        }
+
+        let body: BlockBody = bincode::deserialize(&encoded[..])
+            .map_err(|err| StorageError::CorruptBlock(bsh, BlockFileError::Serialization(err)))?;
+        return Ok(Block::create_from_block_body(body));
+    }
+
+    //
+    // files without a header cannot be verified and are listed
+    // as legacy
+    //
+    fn verify_blocks(&self, report: &mut StorageReport) -> Result<(), StorageError> {
+        let entries = match fs::read_dir(&self.blocks_dir) {
+            Ok(entries) => entries,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(StorageError::Io(err)),
+        };
+
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().map_or(true, |extension| extension != "sai") { continue; }
+
+            let filename = path.to_string_lossy().into_owned();
+            let encoded = fs::read(&path)?;
+
+            if !is_block_file(&encoded[..]) {
+                report.legacy.push(filename);
+                continue;
+            }
+
+            match decode_block_file(&encoded[..]) {
+                Ok(_) => report.verified += 1,
+                Err(err) => report.corrupt.push((filename, err)),
+            }
+        }
+
+        return Ok(());
+    }
+
+    fn remove_incomplete_writes(&self) -> Result<(), StorageError> {
+        for entry in fs::read_dir(&self.blocks_dir)? {
+            let path = entry?.path();
+            if path.extension().map_or(false, |extension| extension == "tmp") {
+                println!("removing incomplete block file {}", path.display());
+                fs::remove_file(&path)?;
+            }
+        }
+        return Ok(());
+    }
+}
+
+
+//
+// bodies in their own column of the RocksDB store, keyed by bsh.
+// the loose .sai files written by earlier versions
+// are still read if a block is not found in the store.
+//
+pub struct RocksDbBlockStore {
+    store: Arc<Store>,
+    legacy_blocks: FileBlockStore,
+}
+
+impl RocksDbBlockStore {
+    pub fn new(store: Arc<Store>, blocks_dir: PathBuf) -> RocksDbBlockStore {
+        return RocksDbBlockStore {
+            store,
+            legacy_blocks: FileBlockStore::new(blocks_dir),
+        };
+    }
+}
+
+impl BlockStore for RocksDbBlockStore {
+    fn write_block(&self, blk: &Block, batch: &mut StoreBatch) -> Result<(), StorageError> {
+        batch.put(COL_BLOCK_BODY, &blk.return_bsh(), &encode_block(blk)?);
+        return Ok(());
+    }
+
+    fn read_block(&self, bsh: [u8; 32]) -> Result<Block, StorageError> {
+        return match self.store.get(COL_BLOCK_BODY, &bsh)? {
+            Some(encoded) => decode_block(bsh, &encoded[..]),
+            None => self.legacy_blocks.read_block(bsh),
+        };
+    }
+
+    fn verify_blocks(&self, report: &mut StorageReport) -> Result<(), StorageError> {
+        for (key, encoded) in self.store.iter(COL_BLOCK_BODY) {
+            match decode_block_file(&encoded[..]) {
+                Ok(_) => report.verified += 1,
+                Err(err) => report.corrupt.push((format!("store:{}", HEXLOWER.encode(&key[..])), err)),
+            }
+        }
+
+        return self.legacy_blocks.verify_blocks(report);
+    }
+
+    fn remove_incomplete_writes(&self) -> Result<(), StorageError> {
+        return self.legacy_blocks.remove_incomplete_writes();
+    }
+}
+
+
+//
+// blocks kept in memory, encoded just as they would be on disk
+//
+pub struct MemoryBlockStore {
+    blocks: RwLock<HashMap<[u8; 32], Vec<u8>>>,
+}
+
+impl MemoryBlockStore {
+    pub fn new() -> MemoryBlockStore {
+        return MemoryBlockStore {
+            blocks: RwLock::new(HashMap::new()),
+        };
+    }
+}
+
+impl BlockStore for MemoryBlockStore {
+    fn write_block(&self, blk: &Block, _batch: &mut StoreBatch) -> Result<(), StorageError> {
+        let encoded = encode_block(blk)?;
+        self.blocks.write().unwrap().insert(blk.return_bsh(), encoded);
+        return Ok(());
+    }
+
+    fn read_block(&self, bsh: [u8; 32]) -> Result<Block, StorageError> {
+        return match self.blocks.read().unwrap().get(&bsh) {
+            Some(encoded) => decode_block(bsh, &encoded[..]),
+            None => Err(StorageError::BlockNotFound(bsh)),
+        };
+    }
+
+    fn verify_blocks(&self, report: &mut StorageReport) -> Result<(), StorageError> {
+        for (bsh, encoded) in self.blocks.read().unwrap().iter() {
+            match decode_block_file(&encoded[..]) {
+                Ok(_) => report.verified += 1,
+                Err(err) => report.corrupt.push((format!("memory:{}", HEXLOWER.encode(bsh)), err)),
+            }
+        }
+        return Ok(());
+    }
+}
@@ -1,66 +1,144 @@
 use serde::{Serialize, Deserialize};
 use std::collections::HashMap;
-use std::sync::RwLock;
+use std::sync::{Arc, RwLock};
 
 use saito_primitives::block::{Block, BlockHeader};
-use saito_primitives::burnfee::BurnFee;
+use saito_primitives::transaction::Transaction;
 
-use crate::storage::Storage;
-use crate::wallet::Wallet;
+use data_encoding::HEXLOWER;
+
+use crate::config::{ChainConfig, DEFAULT_MAX_REORG_DEPTH};
+use crate::storage::{Storage, StorageError};
 use crate::shashmap::Shashmap;
 
 
 //
-// The Blockchain Indices 
+// Chain Reorganization
+//
+// components that need to stay in sync with the longest chain
+// (wallet, mempool, runtime modules) implement this trait and
+// register with the blockchain. they are notified once for every
+// block that is wound onto (longest_chain = true) or unwound from
+// (longest_chain = false) the longest chain, in the order in which
+// the blockchain processes them.
+//
+pub trait ChainReorganization {
+    fn on_chain_reorganization(&mut self, block_header: &BlockHeader, blk: &Block, longest_chain: bool);
+}
+
+pub type ChainSubscriber = Arc<RwLock<dyn ChainReorganization + Send + Sync>>;
+
+
+//
+// The Blockchain Index
 //
-// the contents of this data object are kept in sync so that
-// every element in every vector references the same implicit
-// block, regardless of whether it is on the longest chain or
-// not.
+// the index is a tree of block headers keyed by bsh. every node
+// links to its parent and caches its height and the cumulative
+// burn fee paid along its chain, so that finding the shared
+// ancestor of two blocks and comparing their forks only requires
+// walking back as far as the fork itself goes.
 //
+// blocks whose parent is not indexed when they arrive start a
+// new root with a height of 1.
+//
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct BlockNode {
+    pub header:          BlockHeader,
+    pub parent:          Option<[u8; 32]>,
+    pub height:          u32,
+    pub cumulative_work: u64,
+}
+
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 pub struct BlockchainIndex {
-    blocks:      Vec<BlockHeader>,              // blocks
+    blocks:      HashMap<[u8; 32], BlockNode>,   // blocks by bsh
 }
 
 impl BlockchainIndex {
     pub fn new() -> BlockchainIndex {
         return BlockchainIndex {
-            blocks:      vec![],                 // blocks
+            blocks:      HashMap::new(),
         };
     }
-}
 
-struct ChainNode {
-    pub pos: usize,
-    pub len: u32,
-    pub bf: BurnFee,
-    pub ts: u64,
-    pub prevbsh: [u8; 32],
-    pub bsh: [u8; 32]
-}
+    pub fn insert(&mut self, block_header: BlockHeader) -> BlockNode {
+        let (parent, height, cumulative_work) = match self.blocks.get(&block_header.prevbsh) {
+            Some(parent_node) => (
+                Some(block_header.prevbsh),
+                parent_node.height + 1,
+                parent_node.cumulative_work + block_header.bf.current,
+            ),
+            None => (None, 1, block_header.bf.current),
+        };
+
+        let block_node = BlockNode { header: block_header, parent, height, cumulative_work };
+        self.blocks.insert(block_node.header.bsh, block_node.clone());
+
+        return block_node;
+    }
+
+    pub fn get(&self, bsh: &[u8; 32]) -> Option<&BlockNode> {
+        return self.blocks.get(bsh);
+    }
+
+    pub fn remove(&mut self, bsh: &[u8; 32]) -> Option<BlockNode> {
+        return self.blocks.remove(bsh);
+    }
+
+    pub fn contains(&self, bsh: &[u8; 32]) -> bool {
+        return self.blocks.contains_key(bsh);
+    }
+
+    pub fn len(&self) -> usize {
+        return self.blocks.len();
+    }
+
+    //
+    // put back a node as it was saved
+    //
+    pub fn restore(&mut self, block_node: BlockNode) {
+        self.blocks.insert(block_node.header.bsh, block_node);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &BlockNode> {
+        return self.blocks.values();
+    }
 
-impl ChainNode {
-    pub fn new(
-        pos: usize,
-        len: u32,
-        bf: BurnFee,
-        ts: u64,
-        prevbsh: [u8; 32],
-        bsh: [u8; 32]
-    ) -> ChainNode {
-        return ChainNode { pos, len, bf, ts,  prevbsh, bsh }
-    }
-
-    pub fn default() -> ChainNode {
-        return ChainNode {
-            pos: 0,
-            len: 0,
-            bf: BurnFee::new(0.0, 0),
-            ts: 0,
-            prevbsh: [0; 32],
-            bsh: [0; 32],
+    //
+    // step back whichever node is higher until the two meet. this
+    // returns None if the blocks do not share an indexed ancestor.
+    //
+    pub fn return_common_ancestor(&self, bsh1: [u8; 32], bsh2: [u8; 32]) -> Option<&BlockNode> {
+        let mut node1 = self.blocks.get(&bsh1)?;
+        let mut node2 = self.blocks.get(&bsh2)?;
+
+        while node1.header.bsh != node2.header.bsh {
+            if node1.height >= node2.height {
+                node1 = self.blocks.get(&node1.parent?)?;
+            } else {
+                node2 = self.blocks.get(&node2.parent?)?;
+            }
+        }
+
+        return Some(node1);
+    }
+
+    //
+    // the hashes of the blocks after ancestor_bsh up to and including
+    // tip_bsh, in the order they need to be wound
+    //
+    pub fn return_fork_hashes(&self, ancestor_bsh: [u8; 32], tip_bsh: [u8; 32]) -> Vec<[u8; 32]> {
+        let mut fork_hashes: Vec<[u8; 32]> = vec![];
+        let mut current = self.blocks.get(&tip_bsh);
+
+        while let Some(node) = current {
+            if node.header.bsh == ancestor_bsh { break; }
+            fork_hashes.push(node.header.bsh);
+            current = node.parent.and_then(|parent| self.blocks.get(&parent));
         }
+
+        fork_hashes.reverse();
+        return fork_hashes;
     }
 }
 
@@ -73,15 +151,18 @@ impl ChainNode {
 // longest-chain as well as the material that is sitting off
 // the longest-chain but capable of being switched over.
 //
-#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+// it is saved a block at a time: the node for every block in the
+// index and the chain tip below. the longest-chain maps are
+// rebuilt from these when the blockchain is restored.
+//
+#[derive(Clone)]
 pub struct Blockchain {
 
     index:          BlockchainIndex,
     bsh_lc_hmap:    HashMap<[u8; 32], u8>,
-    bsh_bid_hmap:   HashMap<[u8; 32], u32>,
+    lc_bid_hmap:    HashMap<u32, [u8; 32]>,		// bsh of lc block by bid
 
-    lc_pos_set:     bool,
-    lc_pos:         usize,				// pos of lc
+    lc_bsh:         Option<[u8; 32]>,			// bsh of lc
 
     genesis_ts:	    u64,
     genesis_bid:    u32,
@@ -96,6 +177,39 @@ pub struct Blockchain {
     lowest_acceptable_bsh:	[u8; 32],
     lowest_acceptable_bid:	u32,
 
+    //
+    // these come from config every time we start, so a restored
+    // chain cannot override them
+    //
+    checkpoints:		HashMap<u32, [u8; 32]>,	// bsh required at bid
+    max_reorg_depth:		u32,			// 0 for no limit
+
+    subscribers:		Vec<ChainSubscriber>,
+    missing_blocks:		Vec<[u8; 32]>,		// to be requested from peers
+    previous_lc_bsh:		Option<[u8; 32]>,	// lc to restore if add_block fails
+
+}
+
+//
+// the part of the blockchain that is not kept in the index,
+// saved along with every block
+//
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct ChainTip {
+    pub lc_bsh:                Option<[u8; 32]>,
+
+    pub genesis_ts:            u64,
+    pub genesis_bid:           u32,
+    pub genesis_period:        u32,
+
+    pub last_bsh:              [u8; 32],
+    pub last_bid:              u32,
+    pub last_ts:               u64,
+    pub last_bf:               f32,
+
+    pub lowest_acceptable_ts:  u64,
+    pub lowest_acceptable_bsh: [u8; 32],
+    pub lowest_acceptable_bid: u32,
 }
 
 impl Blockchain {
@@ -105,9 +219,8 @@ impl Blockchain {
 
 	    index:         	   BlockchainIndex::new(),
 	    bsh_lc_hmap:   	   HashMap::new(),
-	    bsh_bid_hmap:  	   HashMap::new(),
-	    lc_pos_set:    	   false,
-	    lc_pos:        	   0,
+	    lc_bid_hmap:  	   HashMap::new(),
+	    lc_bsh:        	   None,
 
 	    genesis_ts:	    	   0,
 	    genesis_bid:    	   0,
@@ -122,11 +235,200 @@ impl Blockchain {
 	    lowest_acceptable_bsh: [0; 32],
 	    lowest_acceptable_bid: 0,
 
+	    checkpoints:	   HashMap::new(),
+	    max_reorg_depth:	   DEFAULT_MAX_REORG_DEPTH,
+
+	    subscribers:	   vec![],
+	    missing_blocks:	   vec![],
+	    previous_lc_bsh:	   None,
+
+        };
+    }
+
+    //
+    // rebuild a blockchain from its saved nodes and chain tip.
+    // every block from the tip back to the first block we hold
+    // must be among the nodes.
+    //
+    pub fn restore(chain_tip: ChainTip, block_nodes: Vec<BlockNode>) -> Result<Blockchain, String> {
+        let mut blockchain = Blockchain::new();
+
+        for block_node in block_nodes {
+            blockchain.bsh_lc_hmap.insert(block_node.header.bsh, 0);
+            blockchain.index.restore(block_node);
+        }
+
+        let mut current = chain_tip.lc_bsh;
+        while let Some(bsh) = current {
+            let block_node = match blockchain.index.get(&bsh) {
+                Some(block_node) => block_node,
+                None => return Err(format!("block {} on the longest chain was not saved", HEXLOWER.encode(&bsh))),
+            };
+            blockchain.bsh_lc_hmap.insert(bsh, 1);
+            blockchain.lc_bid_hmap.insert(block_node.header.bid, bsh);
+            current = block_node.parent;
+        }
+
+        blockchain.lc_bsh                = chain_tip.lc_bsh;
+        blockchain.genesis_ts            = chain_tip.genesis_ts;
+        blockchain.genesis_bid           = chain_tip.genesis_bid;
+        blockchain.genesis_period        = chain_tip.genesis_period;
+        blockchain.last_bsh              = chain_tip.last_bsh;
+        blockchain.last_bid              = chain_tip.last_bid;
+        blockchain.last_ts               = chain_tip.last_ts;
+        blockchain.last_bf               = chain_tip.last_bf;
+        blockchain.lowest_acceptable_ts  = chain_tip.lowest_acceptable_ts;
+        blockchain.lowest_acceptable_bsh = chain_tip.lowest_acceptable_bsh;
+        blockchain.lowest_acceptable_bid = chain_tip.lowest_acceptable_bid;
+
+        return Ok(blockchain);
+    }
+
+    pub fn return_chain_tip(&self) -> ChainTip {
+        return ChainTip {
+            lc_bsh:                self.lc_bsh,
+            genesis_ts:            self.genesis_ts,
+            genesis_bid:           self.genesis_bid,
+            genesis_period:        self.genesis_period,
+            last_bsh:              self.last_bsh,
+            last_bid:              self.last_bid,
+            last_ts:               self.last_ts,
+            last_bf:               self.last_bf,
+            lowest_acceptable_ts:  self.lowest_acceptable_ts,
+            lowest_acceptable_bsh: self.lowest_acceptable_bsh,
+            lowest_acceptable_bid: self.lowest_acceptable_bid,
+        };
+    }
+
+    pub fn return_block_nodes(&self) -> impl Iterator<Item = &BlockNode> {
+        return self.index.iter();
+    }
+
+    //
+    // register a component to be notified whenever a block
+    // is added to or removed from the longest chain
+    //
+    pub fn register_subscriber(&mut self, subscriber: ChainSubscriber) {
+        self.subscribers.push(subscriber);
+    }
+
+    //
+    // load checkpoints and the maximum reorg depth from config.
+    // checkpoints are provided as hex-encoded block hashes.
+    //
+    pub fn apply_chain_config(&mut self, chain_config: &ChainConfig) {
+        for (bid, bsh_hex) in chain_config.checkpoints.iter() {
+            match HEXLOWER.decode(bsh_hex.as_bytes()) {
+                Ok(ref decoded) if decoded.len() == 32 => {
+                    let mut bsh: [u8; 32] = [0; 32];
+                    bsh.copy_from_slice(&decoded[..]);
+                    self.add_checkpoint(*bid, bsh);
+                },
+                _ => println!("ignoring invalid checkpoint for block {}: {}", bid, bsh_hex),
+            }
+        }
+        self.set_max_reorg_depth(chain_config.max_reorg_depth);
+    }
+
+    //
+    // checkpoints and the reorg limit can be inspected and changed
+    // while the node is running (see ChainConfigRequest), so
+    // operators can override them in an emergency
+    //
+    pub fn add_checkpoint(&mut self, bid: u32, bsh: [u8; 32]) {
+        self.checkpoints.insert(bid, bsh);
+    }
+
+    pub fn remove_checkpoint(&mut self, bid: u32) -> Option<[u8; 32]> {
+        return self.checkpoints.remove(&bid);
+    }
+
+    pub fn return_checkpoints(&self) -> &HashMap<u32, [u8; 32]> {
+        return &self.checkpoints;
+    }
+
+    pub fn set_max_reorg_depth(&mut self, max_reorg_depth: u32) {
+        self.max_reorg_depth = max_reorg_depth;
+    }
+
+    pub fn return_max_reorg_depth(&self) -> u32 {
+        return self.max_reorg_depth;
+    }
+
+    //
+    // the checkpoints and reorg limit in force, in the form they
+    // are given in config
+    //
+    pub fn return_chain_config(&self) -> ChainConfig {
+        return ChainConfig {
+            checkpoints: self.checkpoints
+                .iter()
+                .map(|(bid, bsh)| (*bid, HEXLOWER.encode(bsh)))
+                .collect(),
+            max_reorg_depth: self.max_reorg_depth,
         };
     }
 
 
-    pub fn add_block(&mut self, blk: Block, wallet: &RwLock<Wallet>, shashmap: &mut Shashmap) {
+    //
+    // startup consistency check
+    //
+    // block files are written before the chain state saved with
+    // them, and can be lost or damaged afterwards, so the saved
+    // longest chain can end in blocks we are unable to read. we walk back
+    // from the tip to the last block we can read, drop everything
+    // above it from the index and rebuild the shashmap from the
+    // longest chain that is left. returns the number of blocks
+    // dropped.
+    //
+    pub fn check_consistency(&mut self, shashmap: &mut Shashmap, storage: &Storage) -> Result<usize, StorageError> {
+        let mut truncated_hashes: Vec<[u8; 32]> = vec![];
+        let mut current = self.lc_bsh;
+
+        while let Some(bsh) = current {
+            match storage.read_block_from_disk(bsh) {
+                Ok(_) => break,
+                Err(StorageError::BlockNotFound(_)) | Err(StorageError::CorruptBlock(_, _)) => {
+                    truncated_hashes.push(bsh);
+                    current = self.index.get(&bsh).and_then(|block_node| block_node.parent);
+                },
+                Err(err) => return Err(err),
+            }
+        }
+
+        if truncated_hashes.len() == 0 { return Ok(0); }
+
+        println!("blockchain -- truncating {} blocks that were not fully saved", truncated_hashes.len());
+        for bsh in truncated_hashes.iter() {
+            if let Some(block_node) = self.index.remove(bsh) {
+                if self.lc_bid_hmap.get(&block_node.header.bid) == Some(bsh) {
+                    self.lc_bid_hmap.remove(&block_node.header.bid);
+                }
+            }
+            self.bsh_lc_hmap.remove(bsh);
+            self.request_missing_block(*bsh);
+        }
+
+        match current.and_then(|bsh| self.return_block_header_by_hash(bsh)) {
+            Some(lc_header) => self.set_longest_chain_tip(&lc_header),
+            None => self.clear_longest_chain_tip(),
+        }
+
+        let longest_chain_hashes: Vec<[u8; 32]> = self.longest_chain_iter().map(|block_header| block_header.bsh).collect();
+        *shashmap = Shashmap::new();
+        for bsh in longest_chain_hashes.iter() {
+            let blk = storage.read_block_from_disk(*bsh)?;
+            for tx in blk.body.txs.iter() {
+                shashmap.insert_new_transaction(tx);
+                shashmap.spend_transaction(tx, blk.body.id);
+            }
+        }
+
+        return Ok(truncated_hashes.len());
+    }
+
+
+    pub fn add_block(&mut self, blk: Block, shashmap: &mut Shashmap, storage: &Storage) {
 
 	println!("add block");
 
@@ -162,6 +464,16 @@ impl Blockchain {
 	    return;
 	}
 
+	//
+	// ignore blocks that conflict with a checkpoint
+	//
+	if let Some(checkpoint_bsh) = self.checkpoints.get(&blk.body.id) {
+	    if *checkpoint_bsh != blk.return_bsh() {
+		println!("not adding block to blockchain -- block {} conflicts with checkpoint", blk.body.id);
+		return;
+	    }
+	}
+
 
 	/////////////////////////////////
 	// SETTING IMPORTANT VARIABLES //
@@ -170,7 +482,8 @@ impl Blockchain {
 	//
 	// create reference for previous lc
 	//
-	let last_lc_pos = self.lc_pos; 
+	let last_lc_bsh = self.lc_bsh;
+	self.previous_lc_bsh = last_lc_bsh;
 
 
   	//
@@ -232,15 +545,10 @@ impl Blockchain {
 	//
 	if blk.body.ts > self.lowest_acceptable_ts {
       	    if !self.is_bsh_indexed(blk.body.prevbsh)  {
-		if self.lc_pos_set == true {
-        	    if blk.body.id > (self.index.blocks[self.lc_pos].bid - self.genesis_period) {
-
-			//
-			// TODO
-		        //
-		        // send request for missing block
-		        //
+		if self.lc_bsh.is_some() {
+        	    if blk.body.id > self.last_bid.saturating_sub(self.genesis_period) {
 
+			self.request_missing_block(blk.body.prevbsh);
 		    }
         	}
       	    }
@@ -251,21 +559,10 @@ impl Blockchain {
 	// insert indexes //
 	////////////////////
 	//
-	// TODO -- we insert the full block, including transactions but we should
-	// probably avoid this. We could try inserting a ghost block that does 
-	// not have the transaction data but that has the stuff we care about in
-	// order to determine the public chain.
-	//
 	// bf / ts / prevbsh / bsh / bid
 	//
-        let block_header_entry = blk.header();
-
-	//
-	// TODO - binary search on insert point
-	//
-	let pos: usize = self.index.blocks.len();
-        self.bsh_bid_hmap.insert(blk.return_bsh(), blk.body.id);
-        self.index.blocks.insert(pos, block_header_entry);
+	let bsh = blk.return_bsh();
+	let block_node = self.index.insert(blk.header());
 
 
 
@@ -274,215 +571,57 @@ impl Blockchain {
 	////////////////////////////
 
 	let mut i_am_the_longest_chain: u8  = 0;
-        let mut shared_ancestor_pos: usize  = 0;
-        let mut shared_ancestor_pos_found: bool = false;
+        let mut shared_ancestor_bsh: Option<[u8; 32]> = None;
 
+	match last_lc_bsh {
 
-	//
-	// find the shared ancestor position
-	// figure out if i am on the longest chain
-        //
-	if self.index.blocks.len() == 1 {
-	
     	    //
     	    // starting point
     	    //
-    	    if self.last_bid > 0 {
-      		if blk.body.prevbsh == self.last_bsh {
+	    None => {
+    	        if self.last_bid == 0 || blk.body.prevbsh == self.last_bsh {
        		    i_am_the_longest_chain = 1;
       		}
-    	    } else {
-      		i_am_the_longest_chain = 1;
-    	    }
-
-	} else {
-
-	    if blk.body.id >= self.index.blocks[self.lc_pos].bid {
+	    },
 
-    		if blk.body.prevbsh == self.index.blocks[self.lc_pos].bsh {
+	    Some(lc_bsh) => {
+    		if blk.body.prevbsh == lc_bsh {
         	    i_am_the_longest_chain = 1;
       		} else {
-		    
+
                     //
         	    // find the last shared ancestor
         	    //
-                    let mut longest_chain = ChainNode::new(
-                        self.lc_pos,
-                        0,
-        	        self.index.blocks[self.lc_pos].bf.clone(),
-                        self.index.blocks[self.lc_pos].ts,
-                        self.index.blocks[self.lc_pos].prevbsh,
-                        [0; 32],
-                    ); 
-                    let mut new_chain = ChainNode::new(
-                        pos,
-                        0,
-        	        self.index.blocks[pos].bf.clone(),
-                        self.index.blocks[pos].ts,
-                        self.index.blocks[pos].prevbsh,
-                        [0; 32],
-                    ); 
-                    let mut search_node = ChainNode::default();
-                    let mut search_completed: bool = false;
-
-	            if new_chain.ts >= longest_chain.ts {
-	                search_node.pos = new_chain.pos - 1;
-        	    } else {
-          	        search_node.pos = longest_chain.pos - 1;
-         	    }
-
-		    while !search_completed {
-
-			if search_node.pos == 0 { 
-                            search_completed = true; 
-                        }
-
-          	        search_node.bf         = self.index.blocks[search_node.pos].bf.clone();
-          	        search_node.bsh        = self.index.blocks[search_node.pos].bsh;
-          	        search_node.prevbsh    = self.index.blocks[search_node.pos].prevbsh;
-
-			//
-			// we find the common ancestor
-			//
-		        if search_node.bsh == longest_chain.prevbsh 
-                            && search_node.bsh == new_chain.prevbsh {
-			    shared_ancestor_pos = search_node.pos;
-			    shared_ancestor_pos_found = true;
-			    search_completed = true;
-			//
-			// or we keep looking
-			//
-			} else {
-
-            		    if search_node.bsh == longest_chain.prevbsh {
-            		        longest_chain.len += 1; 
-            		        longest_chain.bf.current += self.index.blocks[search_node.pos].bf.current;
-            		        longest_chain.prevbsh = self.index.blocks[search_node.pos].prevbsh;
-            		    }
-            
-            		    if search_node.bsh == new_chain.prevbsh {
-            		        new_chain.len += 1; 
-            		        new_chain.bf.current += self.index.blocks[search_node.pos].bf.current;
-            		        new_chain.prevbsh = self.index.blocks[search_node.pos].prevbsh;
-            		    }
-            
-            		    shared_ancestor_pos = search_node.pos;
-            		    if search_node.pos > 0 { search_node.pos -= 1; }            
-            
-            		    //
-            		    // new chain completely disconnected
-            		    // 
-            		    if shared_ancestor_pos == 1 {
-            		        if [0; 32] == new_chain.prevbsh {
-				    //
-				    // add the block, and escape from this
-				    //
-            		            //await this.addBlockToBlockchainSuccess(newblock, pos, 0);
-				    println!("blockchain - block disconnected from chain");
-            		            return;
-            		        } 
-            		    } 
-
-            		    if shared_ancestor_pos == 0 {
-            		        if longest_chain.prevbsh == new_chain.prevbsh {
-				    //
-				    // add the block, and escape from this
-				    //
-            		            //await this.addBlockToBlockchainSuccess(newblock, pos, 0);
-				    println!("blockchain - block disconnected from chain");
-                                    return
-            		        } 
-            		    } 
-   		        }
-		    }
-
-        	    //
-        	    // at this point, we have a shared ancestor position for
-        	    // our two possible chains, and we need to decide which
-        	    // we are treating as the longest chain.
-        	    //
-        	    if new_chain.len > longest_chain.len 
-                        && new_chain.bf.current >= longest_chain.bf.current
-                        && shared_ancestor_pos_found == true {
-
-		        //
-   		        // to prevent our system from being gamed, we
-   		        // require the attacking chain to have equivalent
-   		        // or greater aggregate burn fee. This ensures that
-   		        // an attacker cannot lower difficulty, pump out a
-   		        // ton of blocks, and then hike the difficulty only
-   		        // at the last moment to claim the longest chain.
-   		        //
-   		        println!("UPDATING LONGEST CHAIN: no voter preference needed");
-   		        i_am_the_longest_chain = 1;
-
-   		    } else {
-
-   		        //
-   		        // to prevent our system from being gamed, we
-   		        // require the attacking chain to have equivalent
-   		        // or greater aggregate burn fee. This ensures that
-   		        // an attacker cannot lower difficulty, pump out a
-   		        // ton of blocks, and then hike the difficulty only
-   		        // at the last moment to claim the longest chain.
-   		        //
-   		        // this is like the option above, except that we
-   		        // have a choice of which block to support.
-   		        //
-   		        if new_chain.len == longest_chain.len 
-                            && new_chain.bf.current >= longest_chain.bf.current
-                            && shared_ancestor_pos_found == true {
-
+		    let longest_chain = self.index.get(&lc_bsh).unwrap().clone();
+
+		    match self.index.return_common_ancestor(bsh, lc_bsh) {
+			None => println!("blockchain - block disconnected from chain"),
+			Some(ancestor) => {
+			    shared_ancestor_bsh = Some(ancestor.header.bsh);
+
+        	            //
+   		            // to prevent our system from being gamed, we
+   		            // require the attacking chain to have equivalent
+   		            // or greater aggregate burn fee. This ensures that
+   		            // an attacker cannot lower difficulty, pump out a
+   		            // ton of blocks, and then hike the difficulty only
+   		            // at the last moment to claim the longest chain.
+   		            //
+   		            // since both chains share the ancestor, comparing
+   		            // height and cumulative work on the tips compares
+   		            // the two forks.
 			    //
-			    // TODO - allow voter preference 
-			    //
-   		            //if (this.app.voter.prefersBlock(newblock, this.returnLatestBlock())) {
+			    // TODO - allow voter preference when the forks
+			    // are the same length
+   		            //
+			    if block_node.height >= longest_chain.height
+				&& block_node.cumulative_work >= longest_chain.cumulative_work {
    		        	println!("UPDATING LONGEST CHAIN: no voter preference needed");
    		                i_am_the_longest_chain = 1;
-   		            //}
-   		        }
-        	    }
+			    }
+			}
+		    }
 	    	}
-	    } else {
-
-      	        println!("blockchain -- add block -- edge case with unordered blocks");
-
-	        //
-	        // this catches an edge case that happens if we ask for blocks starting from
-	        // id = 132, but the first block we RECEIVE is a later block in that chain,
-	        // such as 135 or so.
-	        //
-      		//
-      		// in this case our blockchain class will treat the first block as the starting
-      		// point and we run into issues unless we explicitly reset the blockchain to
-      		// treat block 132 as the proper first block.
-      		//
-      		// so we reset this to our first block and mark it as part of the longest chain
-      		// the network will figure this out in time as further blocks build on it.
-      		//
-      		if blk.body.prevbsh == self.last_bsh && blk.body.prevbsh != [0;32] {
-
-		    //
-	            // reset later blocks
-		    //
-       	 	    for h in (pos+1)..self.index.blocks.len() {
-
-			//
-			// reset LC hashmap
-			//
-        		self.bsh_lc_hmap.insert(self.index.blocks[h].bsh, i_am_the_longest_chain);
-
-			//
-			// TODO - onChainReorganization
-			//
-			// - storage ? - wallet ? - modules ?
-			//
-			// self.onChainReorganization(self.index.blocks[h].body.id, self.index.blocks[h].body.return_bsh(), 0);
-
-        	    }
-
-        	    i_am_the_longest_chain = 1;
-		}
 	    }
 	}
 
@@ -490,116 +629,90 @@ impl Blockchain {
 	//
 	// insert into LC hashmap
 	//
-        self.bsh_lc_hmap.insert(self.index.blocks[pos].bsh, i_am_the_longest_chain);
+        self.bsh_lc_hmap.insert(bsh, i_am_the_longest_chain);
 
 
 	//
 	// update blockchain state variables depending
 	//
 	if i_am_the_longest_chain == 1 {
-	    self.last_bsh  = self.index.blocks[pos].bsh;
-	    self.last_ts   = self.index.blocks[pos].ts;
-	    self.last_bid  = self.index.blocks[pos].bid;
-  	    self.lc_pos = pos;
-	    self.lc_pos_set = true;
+	    self.set_longest_chain_tip(&block_node.header);
         }
 
 	//
 	// old and new chains
 	//
-	let mut new_hash_to_hunt_for: Option<[u8;32]>;
-	let mut old_hash_to_hunt_for: Option<[u8;32]>;
-	let mut new_block_hashes:     Vec<[u8;32]>;
-	let mut old_block_hashes:     Vec<[u8;32]>;
+	let mut new_block_hashes:     Vec<[u8;32]> = vec![];
+	let mut old_block_hashes:     Vec<[u8;32]> = vec![];
 
 
 	//
 	// POTENTIAL LONGEST CHAIN
 	//
-	// TODO
-	//
-	// unclear about the appropriateness of sending the blockchain
-	// to addBlockToBLockchain success in this manner. We are simplifying
-	// so need to check if this is still needed.
-	//
   	// the first block goes directly to addBlockToBlockchainSuccess
   	// in order to avoid it getting inserted into the database with
   	// longest_chain of 0. This is only an issue with the first
   	// block.
   	//
-        if i_am_the_longest_chain == 1 && self.index.blocks.len() == 1 {
+        if i_am_the_longest_chain == 1 && self.index.len() == 1 {
             for tx in blk.body.txs.iter() {
 	        shashmap.spend_transaction(tx, blk.body.id);
 	        shashmap.insert_new_transaction(tx);
 	    }
-            self.add_block_success(blk, wallet, pos, 1, 1);
+            if !self.save_block(&blk, shashmap, storage) {
+                for tx in blk.body.txs.iter() { shashmap.unspend_transaction(tx); }
+                self.add_block_failure(blk, 1, 1);
+                return;
+            }
+            self.add_block_success(blk, storage, 1, 1);
             return;
         } 
         
-	if i_am_the_longest_chain == 1 && self.index.blocks.len() > 1 {
-           
-            let old_hash: Option<[u8; 32]>;
-            if last_lc_pos == pos { 
-                old_hash = None; 
-            } else {
-                old_hash = Some(self.index.blocks[last_lc_pos].bsh);
-            }
-
-	    new_hash_to_hunt_for = Some(blk.return_bsh());
-	    old_hash_to_hunt_for = old_hash;
-	    new_block_hashes     = vec![];
-	    old_block_hashes     = vec![];
-
-	    //
-	    // our block builds on the longest chain
-	    //
-	    if blk.body.prevbsh == old_hash_to_hunt_for.unwrap() || None == old_hash_to_hunt_for {
-                new_block_hashes.push(self.index.blocks[pos].bsh);
-	    }
+	if i_am_the_longest_chain == 1 {
+	    match (last_lc_bsh, shared_ancestor_bsh) {
 
-	    //
-	    // this is a chain reorganization
-	    //
-	    else {
-	        let min_block_idx = shared_ancestor_pos + 1; 
-                let max_block_idx = self.index.blocks.len() ;
-                
-                for i in (min_block_idx..max_block_idx).rev() {
-	            if self.index.blocks[i].bsh == old_hash_to_hunt_for.unwrap() {
-          		old_hash_to_hunt_for = Some(self.index.blocks[i].prevbsh);
-                        old_block_hashes.push(self.index.blocks[i].bsh);
-        	    }
-
-        	    if self.index.blocks[i].bsh == new_hash_to_hunt_for.unwrap() {
-          		new_hash_to_hunt_for = Some(self.index.blocks[i].prevbsh);
-          		new_block_hashes.push(self.index.blocks[i].bsh);
-        	    }
-      		}
+		//
+		// this is a chain reorganization
+		//
+		(Some(old_lc_bsh), Some(shared_ancestor_bsh)) => {
+		    old_block_hashes = self.index.return_fork_hashes(shared_ancestor_bsh, old_lc_bsh);
+		    new_block_hashes = self.index.return_fork_hashes(shared_ancestor_bsh, bsh);
 
-      		old_block_hashes.reverse();
-      		new_block_hashes.reverse();
+		    //
+		    // refuse reorganizations that are deeper than we allow or
+		    // that would unwind a checkpointed block. the new block is
+		    // kept in the index, but off the longest chain.
+		    //
+		    if !self.is_reorganization_allowed(&old_block_hashes) {
+			println!(
+			    "blockchain -- rejecting chain reorganization unwinding {} blocks",
+			    old_block_hashes.len()
+			);
+
+			let old_lc_header = self.index.get(&old_lc_bsh).unwrap().header.clone();
+			self.bsh_lc_hmap.insert(bsh, 0);
+			self.set_longest_chain_tip(&old_lc_header);
+
+			i_am_the_longest_chain = 0;
+			new_block_hashes       = vec![];
+			old_block_hashes       = vec![];
+		    }
+		},
 
+		//
+		// our block builds on the longest chain
+		//
+		_ => new_block_hashes.push(bsh),
 	    }
 	} else {
-
-	    //
-	    // initialize
-            //
-            // TODO
-            //
-            // these shouldn't be blank, but Options
-	    //
             println!("we are not the longest chain (?)");
-	    new_block_hashes     = vec![];
-	    old_block_hashes     = vec![];
 	}
 
 
 	self.validate(
 	    blk,
-            wallet,
             shashmap,
-	    pos,
+            storage,
 	    i_am_the_longest_chain,
 	    new_block_hashes,
 	    old_block_hashes,
@@ -614,9 +727,8 @@ impl Blockchain {
     pub fn validate(
 	&mut self, 
 	blk                    :Block,
-        wallet                 :&RwLock<Wallet>,
         shashmap               :&mut Shashmap,
-	pos		       :usize,
+        storage                :&Storage,
 	i_am_the_longest_chain :u8,
 	new_block_hashes       :Vec<[u8;32]>,
 	old_block_hashes       :Vec<[u8;32]>,
@@ -631,7 +743,7 @@ impl Blockchain {
             //
             // -- force is not added as argment to this function, fix?
             //
-            self.add_block_failure(blk, pos, i_am_the_longest_chain, 0);
+            self.add_block_failure(blk, i_am_the_longest_chain, 0);
             return;
 	}
 
@@ -649,9 +761,8 @@ impl Blockchain {
 	    let obhlen = old_block_hashes.len()-1;
 	    self.unwind_chain(
 	        blk,
-                wallet,
                 shashmap,
-	        pos,
+                storage,
 	        i_am_the_longest_chain,
 	        new_block_hashes,
 	        old_block_hashes,
@@ -662,9 +773,8 @@ impl Blockchain {
 	} else {
 	    self.wind_chain(
 	        blk,
-                wallet,
                 shashmap,
-	        pos,
+                storage,
 	        i_am_the_longest_chain,
 	        new_block_hashes,
 	        old_block_hashes,
@@ -678,9 +788,8 @@ impl Blockchain {
     pub fn unwind_chain(
 	 &mut self,
 	 blk	                :Block,
-         wallet                 :&RwLock<Wallet>,
          shashmap               :&mut Shashmap,
-	 pos		        :usize,
+         storage                :&Storage,
  	 i_am_the_longest_chain :u8,
 	 new_block_hashes       :Vec<[u8;32]>,
 	 old_block_hashes       :Vec<[u8;32]>,
@@ -691,50 +800,56 @@ impl Blockchain {
 
 
         if old_block_hashes.len() > 0 {
-            // TODO, handle block not found result with Option 
-            
-            let old_blk = Storage::read_block_from_disk(old_block_hashes[current_unwind_index]);
+
 	    //
-	    // load old block or list of TX SLIPS
+	    // load old block
 	    //
-	    // await this.returnBlockByHash(old_block_hashes[current_unwind_index], 2);
-	    // if block_does_not_exist {
-
-		//
-		// request missing block
-		//
-
-		//
-		// exit, or rollback with last longest china
-		//
+	    // if we cannot load it we request it from our peers and
+	    // roll back to the last longest chain by winding on the
+	    // blocks we have already unwound.
+	    //
+            let old_blk = match storage.read_block_from_disk(old_block_hashes[current_unwind_index]) {
+		Ok(old_blk) => old_blk,
+		Err(err) => {
+		    println!("blockchain -- aborting chain reorganization: {}", err);
+		    if let StorageError::BlockNotFound(bsh) = err {
+			self.request_missing_block(bsh);
+		    }
 
-	    //}
+		    let rewind_hashes = old_block_hashes[current_unwind_index+1..].to_vec();
+		    if rewind_hashes.len() > 0 {
+			self.wind_chain(
+			    blk,
+			    shashmap,
+			    storage,
+			    i_am_the_longest_chain,
+			    rewind_hashes,
+			    vec![],
+			    force,
+			    1,
+			    0
+			);
+		    } else {
+			self.add_block_failure(blk, i_am_the_longest_chain, force);
+		    }
+		    return;
+		}
+	    };
 
 	    //
 	    // block or data is legit, so run on_chain_reorganization
-	    // this should update the LC index as well
-	    // self.on_chain_reorganization(OLD_BLOCK_DATA);
+	    // this updates the LC index as well
+	    //
+	    self.on_chain_reorganization(&old_blk, false);
+	    self.update_transaction_index(&old_blk, storage, false);
 
 
-	    // if we are the node that produced this block, we catch any transactions
-	    // that were added to it. we want to add these transactions back into our
-	    // mempool once the chain has been rewritten if their inputs are still
-	    // valid.
 	    //
-	    //if (this.app.wallet.returnPublicKey() == blk.block.creator) {
-      		//
-      		// a block that we created is getting undone, so we push all of the
-      		// transactions into a special queue that exists in our mempool for
-      		// us to check once we have finished re-writing the chain.
-      		//
-      		//if (blk.transactions != null) {
-      		//  for (let i = 0; i < blk.transactions.length; i++) {
-      		//    console.log("RECOVERING TRANSACTIONS FROM PREVIOUS BLOCKS");
-      		//    console.log(blk.transactions[i]);
-      		//    this.app.mempool.recoverTransaction(blk.transactions[i]);
-      		//  }
-      		//}
-    	    //}
+	    // the mempool is a subscriber, so the transactions in this block
+	    // are caught there as it is unwound. once the chain has been
+	    // rewritten they are added back into the mempool if their inputs
+	    // are still valid.
+	    //
 
 
 	    //
@@ -753,9 +868,8 @@ impl Blockchain {
 	    if current_unwind_index == 0 {
 	        self.wind_chain(
 	            blk,
-                    wallet,
                     shashmap,
-	            pos,
+                    storage,
 	            i_am_the_longest_chain,
 	            new_block_hashes,
 	            old_block_hashes,
@@ -766,9 +880,8 @@ impl Blockchain {
 	    } else {
 		self.unwind_chain(
 	            blk,
-                    wallet,
                     shashmap,
-	            pos,
+                    storage,
 	            i_am_the_longest_chain,
 	            new_block_hashes,
 	            old_block_hashes,
@@ -785,9 +898,8 @@ impl Blockchain {
 	    //
 	    self.wind_chain(
 	        blk,
-                wallet, 
                 shashmap,
-	        pos,
+                storage,
 	        i_am_the_longest_chain,
 	        new_block_hashes,
 	        old_block_hashes,
@@ -801,9 +913,8 @@ impl Blockchain {
     pub fn wind_chain(
 	&mut self,
 	blk		     :Block,
-        wallet               :&RwLock<Wallet>,
         shashmap             :&mut Shashmap,
-        pos		     :usize,
+        storage              :&Storage,
         i_am_the_longest_chain:u8,
         new_block_hashes     :Vec<[u8;32]>,
         old_block_hashes     :Vec<[u8;32]>,
@@ -812,22 +923,26 @@ impl Blockchain {
         current_wind_index :usize,
     ) {
 
-        let mut this_block_hash = [0; 32];
-
-        // temporary catch when new_block_hashes and old_block_hashes are replaced as Options
-        if new_block_hashes.len() == 0 && old_block_hashes.len() == 0 {
-            self.add_block_success(blk, wallet, pos, i_am_the_longest_chain, force);
+        //
+        // nothing to wind
+        //
+        if new_block_hashes.len() == 0 {
+            if resetting_flag == 0 && self.save_block(&blk, shashmap, storage) {
+                self.add_block_success(blk, storage, i_am_the_longest_chain, force);
+            } else {
+                self.add_block_failure(blk, i_am_the_longest_chain, force);
+            }
             return;
         }
 
-        if new_block_hashes.len() != 0 {
-	   this_block_hash = new_block_hashes[current_wind_index];
-        }
+        let this_block_hash = new_block_hashes[current_wind_index];
 
   	//
   	// we have not saved the latest block to disk yet, so
   	// there's no need to go through the delay of opening
-  	// files from disk.
+  	// files from disk. it is saved here, with the chain state
+  	// it leaves us in, and a failed write rolls back like a bad
+  	// block.
   	//
   	if this_block_hash == blk.return_bsh() {
 
@@ -846,90 +961,51 @@ impl Blockchain {
 	            shashmap.spend_transaction(&tx, blk.body.id);
 	        }
 
-      	        self.add_block_success(blk, wallet, pos, i_am_the_longest_chain, force);
-                return;
-
-            } else {
+                if self.save_block(&blk, shashmap, storage) {
+      	            self.add_block_success(blk, storage, i_am_the_longest_chain, force);
+                    return;
+                }
 
-                if current_wind_index == 0 {
-
-            	    // this is the first block we have tried to add
-            	    // and so we can just roll out the older chain
-            	    // again as it is known good.
-            	    //
-            	    // note that old and new hashes are swapped
-            	    // and the old chain is set as null because
-            	    // we won't move back to it. we also set the
-            	    // resetting_flag to 1 so we know to fork
-            	    // into add_block_failure
-            	    //
-	    	    if old_block_hashes.len() > 0 {
-                        self.wind_chain(
-                    	    blk,
-                            wallet,
-                    	    shashmap,
-                    	    pos,
-                    	    i_am_the_longest_chain,
-                    	    old_block_hashes,
-                    	    new_block_hashes,
-                    	    force,
-                    	    1,
-                    	    0
-                        );
-		        return;
-	    	    } else {
-		        self.add_block_failure(blk, pos, i_am_the_longest_chain, force);
-		        return;
-	    	    }
-	    	} else {
+                for tx in blk.body.txs.iter() {
+                    shashmap.unspend_transaction(&tx);
+                }
+            }
 
-                    //
-                    // we need to unwind some of our previously
-                    // added blocks from the new chain. so we
-                    // swap our hashes to wind/unwind.
-                    //
-                    let mut chain_to_unwind_hashes :Vec<[u8;32]> = vec![];
+            self.abort_wind_chain(
+                blk,
+                shashmap,
+                storage,
+                i_am_the_longest_chain,
+                new_block_hashes,
+                old_block_hashes,
+                force,
+                current_wind_index,
+            );
+            return;
 
-                    //
-                    // remove previous added
-                    //
-                    for h in (current_wind_index)..new_block_hashes.len() {
-                        chain_to_unwind_hashes.push(new_block_hashes[h]);
+        } else {
+	    // rename -- not blk beaause it
+    	    let old_blk = match storage.read_block_from_disk(this_block_hash) {
+                Ok(old_blk) => old_blk,
+                Err(err) => {
+                    println!("blockchain -- aborting chain reorganization: {}", err);
+                    if let StorageError::BlockNotFound(bsh) = err {
+                        self.request_missing_block(bsh);
                     }
 
-                    //
-                    // unwind NEW and wind OLD
-                    //
-                    // note that we are setting the resetting_flag to 1
-                    //
-                    let ctulen = chain_to_unwind_hashes.len();
-                    self.unwind_chain(
+                    self.abort_wind_chain(
                         blk,
-                        wallet,
                         shashmap,
-                        pos,
+                        storage,
                         i_am_the_longest_chain,
+                        new_block_hashes,
                         old_block_hashes,
-                        chain_to_unwind_hashes,
                         force,
-                        1,
-                        ctulen,
-                        );
+                        current_wind_index,
+                    );
                     return;
-	        }
-	    }
-
-        } else {
-	    // rename -- not blk beaause it
-    	    let old_blk = Storage::read_block_from_disk(this_block_hash);
-            // TODO 
-            //
-            // add catch if we cannot find the block
-            // self.add_block_failure(blk, pos, i_am_the_longest_chain, force);
-            // return;
-
-
-
+                }
+            };
 
             //
             // should reference the block we are adding, not blk
@@ -939,7 +1015,8 @@ impl Blockchain {
                 //
                 // on chain reorganization
                 //
-                //self.on_chain_reorganization();
+                self.on_chain_reorganization(&old_blk, true);
+                self.update_transaction_index(&old_blk, storage, true);
 
                 //
                 // spend in shashmap
@@ -950,19 +1027,18 @@ impl Blockchain {
 
                 if current_wind_index == new_block_hashes.len() - 1 {
                     if resetting_flag == 0 {
-                        self.add_block_success(blk, wallet, pos, i_am_the_longest_chain, force);
+                        self.add_block_success(blk, storage, i_am_the_longest_chain, force);
                         return;
                     } else {
-                        self.add_block_failure(blk, pos, i_am_the_longest_chain, force);
+                        self.add_block_failure(blk, i_am_the_longest_chain, force);
                         return;
                     }
                 } else {
 
                     self.wind_chain(
                         blk,
-                        wallet,
                         shashmap,
-                        pos,
+                        storage,
                         i_am_the_longest_chain,
                         new_block_hashes,
                         old_block_hashes,
@@ -973,56 +1049,75 @@ impl Blockchain {
                         return;
                 }
             } else {
+                self.abort_wind_chain(
+                    blk,
+                    shashmap,
+                    storage,
+                    i_am_the_longest_chain,
+                    new_block_hashes,
+                    old_block_hashes,
+                    force,
+                    current_wind_index,
+                );
+            }
+        }
+    }
 
-                if current_wind_index == 0 {
-                    self.wind_chain(
-                        blk,
-                        wallet,
-                        shashmap,
-                        pos,
-                        i_am_the_longest_chain,
-                        old_block_hashes,
-                        vec![],
-                        force,
-                        1,
-                        0,
-                        );
-
-                } else {
-                    //
-                    // we need to unwind some of our previously
-                    // added blocks from the new chain. so we
-                    // swap our hashes to wind/unwind.
-                    //
-                    let mut chain_to_unwind_hashes :Vec<[u8;32]> = vec![];
-
-                    for h in (current_wind_index)..new_block_hashes.len() {
-                        chain_to_unwind_hashes.push(new_block_hashes[h]);
-                    }
-
-                    //
-                    // unwind NEW and wind OLD
-                    //
-                    // note that we are setting the resetting_flag to 1
-                    //
-                    self.unwind_chain(
-                        blk,
-                        wallet,
-                        shashmap,
-                        pos,
-                        i_am_the_longest_chain,
-                        old_block_hashes,
-                        chain_to_unwind_hashes.clone(),
-                        force,
-                        1,
-                        chain_to_unwind_hashes.clone().len(),
-                    );
-                }
-            }
+    //
+    // the block at current_wind_index could not be wound, so we
+    // unwind the blocks we have already wound from the new chain
+    // and wind the old chain back on. the resetting_flag is set so
+    // that we finish in add_block_failure.
+    //
+    fn abort_wind_chain(
+	&mut self,
+	blk                    :Block,
+        shashmap               :&mut Shashmap,
+        storage                :&Storage,
+	i_am_the_longest_chain :u8,
+	new_block_hashes       :Vec<[u8;32]>,
+	old_block_hashes       :Vec<[u8;32]>,
+	force                  :u8,
+        current_wind_index     :usize,
+    ) {
+        if current_wind_index == 0 {
+            //
+            // nothing from the new chain has been wound, so we
+            // can just roll out the older chain again as it is
+            // known good
+            //
+            self.wind_chain(
+                blk,
+                shashmap,
+                storage,
+                i_am_the_longest_chain,
+                old_block_hashes,
+                vec![],
+                force,
+                1,
+                0,
+            );
+        } else {
+            //
+            // unwind NEW and wind OLD
+            //
+            let chain_to_unwind_hashes = new_block_hashes[..current_wind_index].to_vec();
+            let ctulen = chain_to_unwind_hashes.len();
+            self.unwind_chain(
+                blk,
+                shashmap,
+                storage,
+                i_am_the_longest_chain,
+                old_block_hashes,
+                chain_to_unwind_hashes,
+                force,
+                1,
+                ctulen-1,
+            );
         }
     }
 
-    pub fn add_block_success(&mut self, blk: Block, wallet: &RwLock<Wallet>, _pos: usize, i_am_the_longest_chain: u8, _force: u8) {
+    pub fn add_block_success(&mut self, blk: Block, storage: &Storage, i_am_the_longest_chain: u8, _force: u8) {
         println!("SUCCESS ADDING BLOCK");
         
         // 
@@ -1030,40 +1125,18 @@ impl Blockchain {
         //
 
         //
-        // add slips to wallet
+        // pass block data to wallet, mempool and runtime
         //
-        
-        let publickey = wallet.read().unwrap().return_publickey();
-        blk.body.txs
-            .iter() 
-            .for_each(|tx| {
-                tx.return_from_slips()
-                    .iter()
-                    .filter(|slip| slip.return_add() == publickey)
-                    .for_each(move |slip| {
-                        if let Ok(mut wallet_guard) = wallet.write() {
-                            wallet_guard.remove_slip(slip.clone());
-                        }
-                    });
-                tx.return_to_slips()
-                    .iter()
-                    .filter(|slip| slip.return_add() == publickey)
-                    .for_each(move |slip| {
-                        if let Ok(mut wallet_guard) = wallet.write() {
-                            wallet_guard.add_slip(slip.clone());
-                        }
-                    });
-            });
-
-        Storage::write_block_to_disk(blk);
+        // the block is already on disk
+        //
+        if i_am_the_longest_chain == 1 {
+            self.on_chain_reorganization(&blk, true);
+            self.update_transaction_index(&blk, storage, true);
+        }
         println!("Adding block: {:?}", self.return_latest_block_header().unwrap().bsh); 
         println!("lc: {:?}", i_am_the_longest_chain);
         println!("\n\n\n");
 
-        //
-        // pass block data to runtime and run callbacks
-        //
-        
         //
         // save blockchain options
         //
@@ -1082,19 +1155,128 @@ impl Blockchain {
 
     }
 
-    pub fn add_block_failure(&mut self, _blk: Block, _pos: usize, _i_am_the_longest_chain: u8, _force: u8) {
+    pub fn add_block_failure(&mut self, blk: Block, i_am_the_longest_chain: u8, _force: u8) {
 	println!("FAILURE ADDING BLOCK");
 	println!("\n\n\n");
         
         //
         // restore longest chain
+        //
+        if i_am_the_longest_chain == 1 {
+            self.bsh_lc_hmap.insert(blk.return_bsh(), 0);
+
+            match self.previous_lc_bsh.and_then(|bsh| self.return_block_header_by_hash(bsh)) {
+                Some(previous_lc_header) => self.set_longest_chain_tip(&previous_lc_header),
+                None => self.clear_longest_chain_tip(),
+            }
+        }
+
+        //
+        // remove the block from the index. it was never saved, so
+        // it has to be sent to us again before we can add it.
+        //
+        self.index.remove(&blk.return_bsh());
+        self.bsh_lc_hmap.remove(&blk.return_bsh());
+
         //
         // reset miner
         //
         // save state
         //
-        // remove bad block and transactions from chain
-        //
+    }
+
+    //
+    // blocks are written, together with the chain state they leave
+    // us in, before they join the longest chain or reach the
+    // subscribers, so nothing ever refers to a block that is not
+    // on disk
+    //
+    fn save_block(&self, blk: &Block, shashmap: &mut Shashmap, storage: &Storage) -> bool {
+        let block_node = self.index.get(&blk.return_bsh()).unwrap();
+        if let Err(err) = storage.write_block_with_chain_state(blk, block_node, &self.return_chain_tip(), shashmap) {
+            println!("blockchain -- could not save block: {}", err);
+            return false;
+        }
+        return true;
+    }
+
+    //
+    // update the LC index for a block that is being wound onto or
+    // unwound from the longest chain and pass the block along to
+    // every registered subscriber
+    //
+    fn on_chain_reorganization(&mut self, blk: &Block, longest_chain: bool) {
+        let block_header = blk.header();
+        self.bsh_lc_hmap.insert(block_header.bsh, longest_chain as u8);
+
+        if longest_chain {
+            self.lc_bid_hmap.insert(block_header.bid, block_header.bsh);
+        } else if self.lc_bid_hmap.get(&block_header.bid) == Some(&block_header.bsh) {
+            self.lc_bid_hmap.remove(&block_header.bid);
+        }
+
+        for subscriber in self.subscribers.iter() {
+            if let Ok(mut subscriber_guard) = subscriber.write() {
+                subscriber_guard.on_chain_reorganization(&block_header, blk, longest_chain);
+            }
+        }
+    }
+
+    fn update_transaction_index(&self, blk: &Block, storage: &Storage, longest_chain: bool) {
+        let result = if longest_chain {
+            storage.write_transaction_index(blk)
+        } else {
+            storage.remove_transaction_index(blk)
+        };
+
+        if let Err(err) = result {
+            println!("blockchain -- could not update transaction index: {}", err);
+        }
+    }
+
+    //
+    // there is no way to ask our peers for a block yet, so blocks
+    // we need are queued here until the network can fetch them
+    //
+    fn request_missing_block(&mut self, bsh: [u8; 32]) {
+        println!("blockchain -- requesting missing block {}", HEXLOWER.encode(&bsh));
+        if !self.missing_blocks.contains(&bsh) {
+            self.missing_blocks.push(bsh);
+        }
+    }
+
+    pub fn return_missing_blocks(&mut self) -> Vec<[u8; 32]> {
+        return self.missing_blocks.drain(..).collect();
+    }
+
+    fn is_reorganization_allowed(&self, old_block_hashes: &Vec<[u8; 32]>) -> bool {
+        if self.max_reorg_depth > 0 && old_block_hashes.len() as u32 > self.max_reorg_depth {
+            return false;
+        }
+
+        for bsh in old_block_hashes.iter() {
+            if let Some(block_header) = self.return_block_header_by_hash(*bsh) {
+                if self.checkpoints.get(&block_header.bid) == Some(bsh) {
+                    return false;
+                }
+            }
+        }
+
+        return true;
+    }
+
+    fn set_longest_chain_tip(&mut self, block_header: &BlockHeader) {
+	self.last_bsh  = block_header.bsh;
+	self.last_ts   = block_header.ts;
+	self.last_bid  = block_header.bid;
+	self.lc_bsh    = Some(block_header.bsh);
+    }
+
+    fn clear_longest_chain_tip(&mut self) {
+	self.last_bsh  = [0; 32];
+	self.last_ts   = 0;
+	self.last_bid  = 0;
+	self.lc_bsh    = None;
     }
 
     pub fn validate_block(&self, _blk: &Block) -> bool {
@@ -1105,17 +1287,105 @@ impl Blockchain {
 	return self.bsh_lc_hmap.contains_key(&bsh)
     }
 
+    pub fn is_on_longest_chain(&self, bsh: [u8; 32]) -> bool {
+	return self.bsh_lc_hmap.get(&bsh) == Some(&1);
+    }
+
     pub fn return_latest_block_header(&mut self) -> Option<BlockHeader> {
-        if !self.lc_pos_set { 
-	    //return BlockHeader::new(0.0, [0;32], [0;32], 0, 0);
-            //return BlockHeader::default();
-            return None
-	}
-	return Some(self.index.blocks[self.lc_pos].clone());
+        return self.lc_bsh.and_then(|lc_bsh| self.return_block_header_by_hash(lc_bsh));
     }
 
     pub fn return_index_length(&self) -> usize {
-        return self.index.blocks.len();
+        return self.index.len();
+    }
+
+    pub fn return_block_header_by_hash(&self, bsh: [u8; 32]) -> Option<BlockHeader> {
+        return self.index.get(&bsh).map(|block_node| block_node.header.clone());
+    }
+
+    pub fn return_longest_chain_block_header_by_id(&self, bid: u32) -> Option<BlockHeader> {
+        return self.lc_bid_hmap.get(&bid).and_then(|bsh| self.return_block_header_by_hash(*bsh));
+    }
+
+    //
+    // the timestamp of the longest chain block genesis_period blocks
+    // behind the tip, or None while the chain is not that long
+    //
+    pub fn return_genesis_boundary_ts(&self, genesis_period: u64) -> Option<u64> {
+        if self.lc_bsh.is_none() || self.last_bid as u64 <= genesis_period { return None; }
+
+        let genesis_bid = (self.last_bid as u64 - genesis_period) as u32;
+        return self.return_longest_chain_block_header_by_id(genesis_bid).map(|block_header| block_header.ts);
+    }
+
+    //
+    // the index only holds headers, so full blocks are
+    // fetched through storage
+    //
+    pub fn return_block_by_hash(&self, bsh: [u8; 32], storage: &Storage) -> Result<Arc<Block>, StorageError> {
+        if !self.index.contains(&bsh) { return Err(StorageError::BlockNotFound(bsh)); }
+        return storage.read_block_from_disk(bsh);
+    }
+
+    //
+    // returns a transaction on the longest chain along with its
+    // number of confirmations, counting the block it is in
+    //
+    pub fn find_transaction(&self, tx_hash: [u8; 32], storage: &Storage) -> Result<Option<(Transaction, u32)>, StorageError> {
+        let (bsh, position) = match storage.read_transaction_index(tx_hash)? {
+            Some(indexed) => indexed,
+            None => return Ok(None),
+        };
+
+        let block_header = match self.return_block_header_by_hash(bsh) {
+            Some(block_header) => block_header,
+            None => return Ok(None),
+        };
+        if !self.is_on_longest_chain(bsh) { return Ok(None); }
+
+        let blk = storage.read_block_from_disk(bsh)?;
+        let tx = match blk.body.txs.get(position as usize) {
+            Some(tx) => tx.clone(),
+            None => return Ok(None),
+        };
+
+        return Ok(Some((tx, self.last_bid - block_header.bid + 1)));
+    }
+
+    //
+    // iterate over the headers of the longest chain, from the
+    // earliest block we have indexed up to the latest block
+    //
+    pub fn longest_chain_iter(&self) -> LongestChainIterator {
+        return LongestChainIterator {
+            blockchain: self,
+            bid: self.lowest_acceptable_bid,
+        };
+    }
+
+    //
+    // returns up to n ancestors of the block with this hash,
+    // starting with its parent and moving back towards genesis
+    //
+    pub fn return_ancestors(&self, bsh: [u8; 32], n: usize) -> Vec<BlockHeader> {
+        let mut ancestors: Vec<BlockHeader> = vec![];
+        let mut current = self.index.get(&bsh);
+
+        while ancestors.len() < n {
+            match current.and_then(|node| node.parent).and_then(|parent| self.index.get(&parent)) {
+                Some(parent_node) => {
+                    ancestors.push(parent_node.header.clone());
+                    current = Some(parent_node);
+                },
+                None => break,
+            }
+        }
+
+        return ancestors;
+    }
+
+    pub fn return_common_ancestor(&self, bsh1: [u8; 32], bsh2: [u8; 32]) -> Option<BlockHeader> {
+        return self.index.return_common_ancestor(bsh1, bsh2).map(|block_node| block_node.header.clone());
     }
 
     pub fn return_heartbeat(&self) -> u64 {
@@ -1131,15 +1401,56 @@ impl Blockchain {
 
 }
 
+pub struct LongestChainIterator<'a> {
+    blockchain: &'a Blockchain,
+    bid: u32,
+}
+
+impl<'a> Iterator for LongestChainIterator<'a> {
+    type Item = BlockHeader;
+
+    fn next(&mut self) -> Option<BlockHeader> {
+        if self.blockchain.lc_bsh.is_none() || self.bid > self.blockchain.last_bid { return None; }
+
+        let block_header = self.blockchain.return_longest_chain_block_header_by_id(self.bid);
+        self.bid += 1;
+        return block_header;
+    }
+}
+
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use saito_primitives::slip::{Slip};
-    use saito_primitives::transaction::{Transaction};
+    use saito_primitives::slip::{Slip, SlipSpentStatus};
+    use saito_primitives::transaction::{Transaction, TransactionBroadcastType};
     use saito_primitives::crypto::{generate_keys, PublicKey};
+    use crate::address_index::AddressIndex;
+    use crate::block_store::{BlockStore, BlockStoreBackend};
+    use crate::mempool::Mempool;
+    use crate::storage::{StorageReport, TempPath};
+    use crate::store::StoreBatch;
 
-    use std::{thread,time};
+
+    //
+    // a chain of length blocks on top of prevbsh, starting at
+    // first_bid. timestamps are set here rather than read from the
+    // clock, so forks built on the same parent get their own hashes.
+    //
+    fn create_chain(publickey: PublicKey, prevbsh: [u8; 32], first_bid: u32, length: u32, first_ts: u64) -> Vec<Block> {
+        let mut blocks: Vec<Block> = vec![];
+        let mut prevbsh = prevbsh;
+
+        for i in 0..length {
+            let mut blk = Block::new(publickey, prevbsh);
+            blk.body.id = first_bid + i;
+            blk.body.ts = first_ts + i as u64;
+            prevbsh = blk.return_bsh();
+            blocks.push(blk);
+        }
+
+        return blocks;
+    }
 
     fn create_new_transaction(publickey: PublicKey, amt: u64) -> Transaction {
         let mut tx: Transaction = Transaction::new();
@@ -1157,15 +1468,16 @@ mod test {
 
         let mut blockchain = Blockchain::new();
         let mut shashmap = Shashmap::new();
+        let storage = Storage::in_memory();
 
         let mut blk = Block::new(publickey, [0; 32]);
         blk.body.id = 1; 
         blk.is_valid = 1;
 
         let blk_header = blk.header();
-        blockchain.add_block(blk, &mut shashmap);
+        blockchain.add_block(blk, &mut shashmap, &storage);
 
-        assert_eq!(blockchain.return_latest_block_header(), blk_header);
+        assert_eq!(blockchain.return_latest_block_header(), Some(blk_header));
     }
 
     #[test]
@@ -1175,6 +1487,7 @@ mod test {
 
         let mut blockchain = Blockchain::new();
         let mut shashmap = Shashmap::new();
+        let storage = Storage::in_memory();
 
         let mut blk = Block::new(publickey, [0; 32]);
 
@@ -1192,7 +1505,7 @@ mod test {
         blk.body.id = 1;
         blk.is_valid = 1;
 
-        blockchain.add_block(blk, &mut shashmap);
+        blockchain.add_block(blk, &mut shashmap, &storage);
         
         assert_eq!(shashmap.return_value(slip.return_signature_source()), Some(&1));
     }
@@ -1203,6 +1516,7 @@ mod test {
 
         let mut blockchain = Blockchain::new();
         let mut shashmap = Shashmap::new();
+        let storage = Storage::in_memory();
 
         let mut blk1 = Block::new(publickey, [0; 32]);
         blk1.body.id = 1;
@@ -1231,28 +1545,33 @@ mod test {
         blk7.body.id = 7;
         blk7.set_transactions(&mut vec![create_new_transaction(publickey, 7_000)]);
 
-        thread::sleep(time::Duration::from_millis(1000));
+        // the fork is stamped a second later so its blocks get their own hashes
+        let fork_ts = blk7.body.ts + 1_000;
 
         let mut blk8 = Block::new(publickey, blk3.return_bsh());
         blk8.body.id = 4;
+        blk8.body.ts = fork_ts;
         blk8.set_transactions(&mut vec![create_new_transaction(publickey, 8_000)]);
 
         let mut blk9 = Block::new(publickey, blk8.return_bsh());
         blk9.body.id = 5;
+        blk9.body.ts = fork_ts;
         blk9.set_transactions(&mut vec![create_new_transaction(publickey, 9_000)]);
 
         let mut blk10 = Block::new(publickey, blk9.return_bsh());
         blk10.body.id = 6;
+        blk10.body.ts = fork_ts;
         blk10.set_transactions(&mut vec![create_new_transaction(publickey, 10_000)]);
 
         let mut blk11 = Block::new(publickey, blk10.return_bsh());
         blk11.body.id = 7;
+        blk11.body.ts = fork_ts;
         blk11.set_transactions(&mut vec![create_new_transaction(publickey, 11_000)]);
 
         let blocks = vec![blk1, blk2, blk3, blk4, blk5, blk6, blk7, blk8, blk9, blk10, blk11];
 
         for blk in blocks {
-            blockchain.add_block(blk, &mut shashmap);
+            blockchain.add_block(blk, &mut shashmap, &storage);
         }
 
         assert_eq!(shashmap.return_value(create_new_transaction(publickey, 4_000).return_from_slips()[0].return_signature_source()), Some(&-1));
@@ -1267,4 +1586,580 @@ mod test {
 
         
     }
+
+    #[test]
+    fn test_wind_unwind_chain_and_back() {
+        let (_, publickey) = generate_keys();
+
+        let mut blockchain = Blockchain::new();
+        let mut shashmap = Shashmap::new();
+        let storage = Storage::in_memory();
+
+        let mut blocks = create_chain(publickey, [0; 32], 1, 5, 1_000);
+        let mut fork = create_chain(publickey, blocks[1].return_bsh(), 3, 2, 2_000);
+
+        blocks[2].set_transactions(&mut vec![create_new_transaction(publickey, 3_000)]);
+        blocks[4].set_transactions(&mut vec![create_new_transaction(publickey, 5_000)]);
+        fork[0].set_transactions(&mut vec![create_new_transaction(publickey, 8_000)]);
+
+        let blk5_header = blocks[4].header();
+        let fork_blk4_header = fork[1].header();
+
+        let mut blocks = blocks.into_iter();
+        for blk in blocks.by_ref().take(3).chain(fork.into_iter()) {
+            blockchain.add_block(blk, &mut shashmap, &storage);
+        }
+
+        // the fork takes over
+        assert_eq!(blockchain.return_latest_block_header(), Some(fork_blk4_header));
+        assert_eq!(shashmap.return_value(create_new_transaction(publickey, 3_000).return_from_slips()[0].return_signature_source()), Some(&-1));
+        assert_eq!(shashmap.return_value(create_new_transaction(publickey, 8_000).return_from_slips()[0].return_signature_source()), Some(&3));
+
+        for blk in blocks {
+            blockchain.add_block(blk, &mut shashmap, &storage);
+        }
+
+        // and the original chain grows past it and takes back over
+        assert_eq!(blockchain.return_latest_block_header(), Some(blk5_header));
+        assert_eq!(shashmap.return_value(create_new_transaction(publickey, 3_000).return_from_slips()[0].return_signature_source()), Some(&3));
+        assert_eq!(shashmap.return_value(create_new_transaction(publickey, 5_000).return_from_slips()[0].return_signature_source()), Some(&5));
+        assert_eq!(shashmap.return_value(create_new_transaction(publickey, 8_000).return_from_slips()[0].return_signature_source()), Some(&-1));
+    }
+
+    struct ReorganizationRecorder {
+        wound: Vec<[u8; 32]>,
+        unwound: Vec<[u8; 32]>,
+    }
+
+    impl ChainReorganization for ReorganizationRecorder {
+        fn on_chain_reorganization(&mut self, block_header: &BlockHeader, _blk: &Block, longest_chain: bool) {
+            if longest_chain {
+                self.wound.push(block_header.bsh);
+            } else {
+                self.unwound.push(block_header.bsh);
+            }
+        }
+    }
+
+    #[test]
+    fn test_chain_reorganization_subscribers() {
+        let (_, publickey) = generate_keys();
+
+        let mut blockchain = Blockchain::new();
+        let mut shashmap = Shashmap::new();
+        let storage = Storage::in_memory();
+
+        let recorder = Arc::new(RwLock::new(ReorganizationRecorder { wound: vec![], unwound: vec![] }));
+        blockchain.register_subscriber(recorder.clone());
+
+        let blocks = create_chain(publickey, [0; 32], 1, 3, 1_000);
+        let fork = create_chain(publickey, blocks[1].return_bsh(), 3, 2, 2_000);
+
+        let blk3_bsh = blocks[2].return_bsh();
+        let fork_blk4_bsh = fork[1].return_bsh();
+
+        for blk in blocks.into_iter().chain(fork.into_iter()) {
+            blockchain.add_block(blk, &mut shashmap, &storage);
+        }
+
+        let recorder = recorder.read().unwrap();
+        assert_eq!(recorder.unwound, vec![blk3_bsh]);
+        assert_eq!(recorder.wound.last(), Some(&fork_blk4_bsh));
+        assert_eq!(recorder.wound.len(), 5);
+    }
+
+    #[test]
+    fn test_recover_transactions_into_mempool() {
+        let (_, publickey) = generate_keys();
+
+        let mut blockchain = Blockchain::new();
+        let mut shashmap = Shashmap::new();
+        let storage = Storage::in_memory();
+
+        let mempool = Arc::new(RwLock::new(Mempool::new()));
+        blockchain.register_subscriber(mempool.clone());
+
+        let mut blocks = create_chain(publickey, [0; 32], 1, 3, 1_000);
+        let fork = create_chain(publickey, blocks[1].return_bsh(), 3, 2, 2_000);
+
+        blocks[1].set_transactions(&mut vec![create_new_transaction(publickey, 2_000)]);
+
+        let mut golden_tx = create_new_transaction(publickey, 3_000);
+        golden_tx.set_tx_type(TransactionBroadcastType::GoldenTicket);
+
+        blocks[2].set_transactions(&mut vec![create_new_transaction(publickey, 4_000), golden_tx]);
+        let recoverable_tx = blocks[2].body.txs[0].clone();
+
+        for blk in blocks.into_iter().chain(fork.into_iter()) {
+            blockchain.add_block(blk, &mut shashmap, &storage);
+        }
+
+        let mut mempool = mempool.write().unwrap();
+        mempool.recover_transactions(&shashmap);
+
+        assert_eq!(mempool.return_transactions(), vec![recoverable_tx]);
+    }
+
+    #[test]
+    fn test_blockchain_persistence() {
+        let (_, publickey) = generate_keys();
+
+        let mut blockchain = Blockchain::new();
+        let mut shashmap = Shashmap::new();
+        let storage = Storage::in_memory();
+
+        let mut blocks = create_chain(publickey, [0; 32], 1, 3, 1_000);
+        let mut fork = create_chain(publickey, blocks[1].return_bsh(), 3, 2, 2_000);
+
+        blocks[2].set_transactions(&mut vec![create_new_transaction(publickey, 3_000)]);
+        fork[1].set_transactions(&mut vec![create_new_transaction(publickey, 4_000)]);
+
+        let unwound_slip = blocks[2].body.txs[0].return_from_slips()[0].clone();
+        let wound_slip = fork[1].body.txs[0].return_from_slips()[0].clone();
+        let blk3_bsh = blocks[2].return_bsh();
+
+        // every block is saved with the chain state as it is added
+        for blk in blocks.into_iter().chain(fork.into_iter()) {
+            blockchain.add_block(blk, &mut shashmap, &storage);
+        }
+
+        let (restored_blockchain, restored_shashmap) = storage.read_blockchain_from_disk().unwrap().unwrap();
+
+        assert_eq!(restored_blockchain.return_chain_tip(), blockchain.return_chain_tip());
+        assert_eq!(restored_blockchain.return_index_length(), 5);
+        assert!(!restored_blockchain.is_on_longest_chain(blk3_bsh));
+        assert_eq!(
+            restored_blockchain.longest_chain_iter().collect::<Vec<BlockHeader>>(),
+            blockchain.longest_chain_iter().collect::<Vec<BlockHeader>>()
+        );
+        assert_eq!(restored_shashmap.return_value(unwound_slip.return_signature_source()), Some(&-1));
+        assert_eq!(restored_shashmap.return_value(wound_slip.return_signature_source()), Some(&4));
+    }
+
+    #[test]
+    fn test_unreadable_chain_state() {
+        let (_, publickey) = generate_keys();
+
+        let mut blockchain = Blockchain::new();
+        let mut shashmap = Shashmap::new();
+        let storage = Storage::in_memory();
+        assert!(storage.read_blockchain_from_disk().unwrap().is_none());
+
+        let blocks = create_chain(publickey, [0; 32], 1, 2, 1_000);
+        let blk2_bsh = blocks[1].return_bsh();
+        for blk in blocks {
+            blockchain.add_block(blk, &mut shashmap, &storage);
+        }
+
+        // the tip refers to a block whose node is gone
+        blockchain.index.remove(&blk2_bsh);
+        storage.write_blockchain_to_disk(&blockchain, &mut shashmap).unwrap();
+
+        match storage.read_blockchain_from_disk() {
+            Err(StorageError::CorruptChainState(_)) => {},
+            _ => panic!("expected the saved chain state to be rejected"),
+        }
+    }
+
+    #[test]
+    fn test_chain_queries() {
+        let (_, publickey) = generate_keys();
+
+        let mut blockchain = Blockchain::new();
+        let mut shashmap = Shashmap::new();
+        let storage = Storage::in_memory();
+
+        let blocks = create_chain(publickey, [0; 32], 1, 3, 1_000);
+        let fork = create_chain(publickey, blocks[1].return_bsh(), 3, 2, 2_000);
+
+        let blk1_bsh = blocks[0].return_bsh();
+        let blk2_bsh = blocks[1].return_bsh();
+        let blk3_bsh = blocks[2].return_bsh();
+        let fork_blk3_bsh = fork[0].return_bsh();
+        let fork_blk4_bsh = fork[1].return_bsh();
+
+        for blk in blocks.into_iter().chain(fork.into_iter()) {
+            blockchain.add_block(blk, &mut shashmap, &storage);
+        }
+
+        assert_eq!(blockchain.return_block_header_by_hash(blk3_bsh).unwrap().bid, 3);
+        assert_eq!(blockchain.return_longest_chain_block_header_by_id(3).unwrap().bsh, fork_blk3_bsh);
+        assert_eq!(blockchain.return_block_by_hash(fork_blk4_bsh, &storage).unwrap().return_bsh(), fork_blk4_bsh);
+
+        let longest_chain: Vec<[u8; 32]> = blockchain.longest_chain_iter().map(|header| header.bsh).collect();
+        assert_eq!(longest_chain, vec![blk1_bsh, blk2_bsh, fork_blk3_bsh, fork_blk4_bsh]);
+
+        let ancestors: Vec<[u8; 32]> = blockchain.return_ancestors(fork_blk4_bsh, 2).iter().map(|header| header.bsh).collect();
+        assert_eq!(ancestors, vec![fork_blk3_bsh, blk2_bsh]);
+
+        assert_eq!(blockchain.return_common_ancestor(blk3_bsh, fork_blk4_bsh).unwrap().bsh, blk2_bsh);
+    }
+
+    #[test]
+    fn test_genesis_boundary_ts() {
+        let (_, publickey) = generate_keys();
+
+        let mut blockchain = Blockchain::new();
+        let mut shashmap = Shashmap::new();
+        let storage = Storage::in_memory();
+
+        for blk in create_chain(publickey, [0; 32], 1, 4, 1_000) {
+            blockchain.add_block(blk, &mut shashmap, &storage);
+        }
+
+        // block 4 is the tip, so a period of 2 puts the boundary at block 2
+        assert_eq!(blockchain.return_genesis_boundary_ts(2), Some(1_001));
+        assert_eq!(blockchain.return_genesis_boundary_ts(3), Some(1_000));
+        assert_eq!(blockchain.return_genesis_boundary_ts(4), None);
+    }
+
+    #[test]
+    fn test_checkpoints_and_max_reorg_depth() {
+        let (_, publickey) = generate_keys();
+
+        let mut blockchain = Blockchain::new();
+        let mut shashmap = Shashmap::new();
+        let storage = Storage::in_memory();
+
+        blockchain.set_max_reorg_depth(1);
+
+        let blocks = create_chain(publickey, [0; 32], 1, 3, 1_000);
+        blockchain.add_checkpoint(1, blocks[0].return_bsh());
+
+        let conflicting_blk1 = create_chain(publickey, [0; 32], 1, 1, 2_000).remove(0);
+        let fork = create_chain(publickey, blocks[0].return_bsh(), 2, 3, 2_000);
+
+        let blk3_header = blocks[2].header();
+        let conflicting_bsh = conflicting_blk1.return_bsh();
+
+        for blk in blocks.into_iter().chain(vec![conflicting_blk1].into_iter()).chain(fork.into_iter()) {
+            blockchain.add_block(blk, &mut shashmap, &storage);
+        }
+
+        // the fork would unwind two blocks, so we stay where we are
+        assert!(!blockchain.is_bsh_indexed(conflicting_bsh));
+        assert_eq!(blockchain.return_latest_block_header(), Some(blk3_header));
+    }
+
+    #[test]
+    fn test_apply_chain_config() {
+        let mut blockchain = Blockchain::new();
+
+        let mut chain_config = ChainConfig::default();
+        chain_config.checkpoints.insert(5, HEXLOWER.encode(&[1; 32]));
+        chain_config.checkpoints.insert(6, String::from("not a block hash"));
+        chain_config.max_reorg_depth = 10;
+
+        blockchain.apply_chain_config(&chain_config);
+        chain_config.checkpoints.remove(&6);
+        assert_eq!(blockchain.return_chain_config(), chain_config);
+
+        // a saved chain does not carry them across restarts
+        let (_, publickey) = generate_keys();
+        let mut shashmap = Shashmap::new();
+        let storage = Storage::in_memory();
+        blockchain.add_block(Block::new(publickey, [0; 32]), &mut shashmap, &storage);
+
+        let (restored_blockchain, _) = storage.read_blockchain_from_disk().unwrap().unwrap();
+        assert_eq!(restored_blockchain.return_checkpoints().len(), 0);
+    }
+
+    #[test]
+    fn test_find_transaction() {
+        let (_, publickey) = generate_keys();
+
+        let mut blockchain = Blockchain::new();
+        let mut shashmap = Shashmap::new();
+        let storage = Storage::in_memory();
+
+        let mut blocks = create_chain(publickey, [0; 32], 1, 3, 1_000);
+        let fork = create_chain(publickey, blocks[1].return_bsh(), 3, 2, 2_000);
+
+        let confirmed_tx = create_new_transaction(publickey, 2_500);
+        let confirmed_tx_hash = confirmed_tx.return_hash();
+        blocks[1].set_transactions(&mut vec![create_new_transaction(publickey, 2_000), confirmed_tx]);
+        blocks[2].set_transactions(&mut vec![create_new_transaction(publickey, 3_000)]);
+
+        let confirmed_tx = blocks[1].body.txs[1].clone();
+        let unwound_tx = blocks[2].body.txs[0].clone();
+
+        for blk in blocks {
+            blockchain.add_block(blk, &mut shashmap, &storage);
+        }
+
+        // the hash the transaction had before it was bundled finds it
+        assert_eq!(blockchain.find_transaction(confirmed_tx_hash, &storage).unwrap(), Some((confirmed_tx.clone(), 2)));
+        assert_eq!(blockchain.find_transaction(unwound_tx.return_hash(), &storage).unwrap(), Some((unwound_tx.clone(), 1)));
+
+        for blk in fork {
+            blockchain.add_block(blk, &mut shashmap, &storage);
+        }
+
+        assert_eq!(blockchain.find_transaction(confirmed_tx_hash, &storage).unwrap(), Some((confirmed_tx, 3)));
+        assert_eq!(blockchain.find_transaction(unwound_tx.return_hash(), &storage).unwrap(), None);
+    }
+
+    fn create_payment_transaction(publickey: PublicKey, amt: u64) -> Transaction {
+        let mut tx: Transaction = Transaction::new();
+        let mut slip: Slip = Slip::new(publickey);
+
+        slip.set_amt(amt);
+        tx.add_to_slip(slip);
+
+        return tx;
+    }
+
+    #[test]
+    fn test_address_index() {
+        let (_, publickey) = generate_keys();
+        let (_, other_publickey) = generate_keys();
+
+        let mut blockchain = Blockchain::new();
+        let mut shashmap = Shashmap::new();
+        let storage = Storage::in_memory();
+
+        let address_index = Arc::new(RwLock::new(AddressIndex::new(storage.clone())));
+        blockchain.register_subscriber(address_index.clone());
+
+        let mut blocks = create_chain(publickey, [0; 32], 1, 2, 1_000);
+        let mut fork = create_chain(publickey, blocks[0].return_bsh(), 2, 2, 2_000);
+
+        blocks[0].set_transactions(&mut vec![create_payment_transaction(publickey, 1_000)]);
+        blocks[1].set_transactions(&mut vec![
+            create_payment_transaction(publickey, 2_000),
+            create_payment_transaction(other_publickey, 5_000),
+        ]);
+        fork[0].set_transactions(&mut vec![create_payment_transaction(publickey, 3_000)]);
+
+        for blk in blocks {
+            blockchain.add_block(blk, &mut shashmap, &storage);
+        }
+
+        {
+            let address_index = address_index.read().unwrap();
+            assert_eq!(address_index.return_balance(&publickey).unwrap(), 3_000);
+            assert_eq!(address_index.return_balance(&other_publickey).unwrap(), 5_000);
+
+            let history: Vec<u32> = address_index.return_history(&publickey).unwrap().iter().map(|address_slip| address_slip.bid).collect();
+            assert_eq!(history, vec![1, 2]);
+        }
+
+        for blk in fork {
+            blockchain.add_block(blk, &mut shashmap, &storage);
+        }
+
+        let address_index = address_index.read().unwrap();
+        assert_eq!(address_index.return_balance(&publickey).unwrap(), 4_000);
+        assert_eq!(address_index.return_balance(&other_publickey).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_address_index_spend_and_unwind() {
+        let (_, publickey) = generate_keys();
+        let (_, other_publickey) = generate_keys();
+
+        let mut blockchain = Blockchain::new();
+        let mut shashmap = Shashmap::new();
+        let storage = Storage::in_memory();
+
+        let address_index = Arc::new(RwLock::new(AddressIndex::new(storage.clone())));
+        blockchain.register_subscriber(address_index.clone());
+
+        let mut blocks = create_chain(publickey, [0; 32], 1, 2, 1_000);
+        let fork = create_chain(publickey, blocks[0].return_bsh(), 2, 2, 2_000);
+
+        blocks[0].set_transactions(&mut vec![create_payment_transaction(publickey, 1_000)]);
+
+        let mut spend_tx = create_payment_transaction(other_publickey, 1_000);
+        spend_tx.add_from_slip(blocks[0].body.txs[0].return_to_slips()[0].clone());
+        blocks[1].set_transactions(&mut vec![spend_tx]);
+
+        for blk in blocks {
+            blockchain.add_block(blk, &mut shashmap, &storage);
+        }
+
+        {
+            let address_index = address_index.read().unwrap();
+            let address_info = address_index.return_info(&publickey).unwrap();
+            assert_eq!(address_info.balance, 0);
+            assert_eq!(address_info.history[0].spent_status, SlipSpentStatus::Spent);
+            assert_eq!(address_index.return_balance(&other_publickey).unwrap(), 1_000);
+        }
+
+        for blk in fork {
+            blockchain.add_block(blk, &mut shashmap, &storage);
+        }
+
+        let address_index = address_index.read().unwrap();
+        let address_info = address_index.return_info(&publickey).unwrap();
+        assert_eq!(address_info.balance, 1_000);
+        assert_eq!(address_info.history[0].spent_status, SlipSpentStatus::Unspent);
+        assert_eq!(address_index.return_balance(&other_publickey).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_check_consistency() {
+        let (_, publickey) = generate_keys();
+
+        let mut blockchain = Blockchain::new();
+        let mut shashmap = Shashmap::new();
+        let storage = Storage::in_memory();
+
+        let mut blk1 = Block::new(publickey, [0; 32]);
+        blk1.body.id = 1;
+
+        let mut blk2 = Block::new(publickey, blk1.return_bsh());
+        blk2.body.id = 2;
+        blk2.set_transactions(&mut vec![create_new_transaction(publickey, 2_000)]);
+
+        let mut blk3 = Block::new(publickey, blk2.return_bsh());
+        blk3.body.id = 3;
+        blk3.set_transactions(&mut vec![create_new_transaction(publickey, 3_000)]);
+
+        let blk2_slip = blk2.body.txs[0].return_from_slips()[0].clone();
+        let blk3_slip = blk3.body.txs[0].return_from_slips()[0].clone();
+        let blk2_header = blk2.header();
+        let blk3_bsh = blk3.return_bsh();
+
+        // the blocks that made it to disk before the crash
+        let crashed_storage = Storage::in_memory();
+        crashed_storage.write_block_to_disk(&blk1).unwrap();
+        crashed_storage.write_block_to_disk(&blk2).unwrap();
+
+        for blk in vec![blk1, blk2, blk3] {
+            blockchain.add_block(blk, &mut shashmap, &storage);
+        }
+
+        assert_eq!(blockchain.check_consistency(&mut shashmap, &storage).unwrap(), 0);
+        assert_eq!(blockchain.check_consistency(&mut shashmap, &crashed_storage).unwrap(), 1);
+
+        assert_eq!(blockchain.return_latest_block_header(), Some(blk2_header));
+        assert!(!blockchain.is_bsh_indexed(blk3_bsh));
+        assert_eq!(shashmap.return_value(blk2_slip.return_signature_source()), Some(&2));
+        assert_eq!(shashmap.return_value(blk3_slip.return_signature_source()), None);
+    }
+
+    #[test]
+    fn test_check_consistency_with_truncated_legacy_block() {
+        let (_, publickey) = generate_keys();
+
+        let temp_dir = TempPath::new("test_truncated_legacy_block");
+        let storage = Storage::open_with_backend(temp_dir.path.to_str().unwrap(), BlockStoreBackend::File).unwrap();
+        storage.set_block_cache_capacity(0);
+
+        let mut blockchain = Blockchain::new();
+        let mut shashmap = Shashmap::new();
+
+        let blocks = create_chain(publickey, [0; 32], 1, 2, 1_000);
+        let blk1_header = blocks[0].header();
+        let blk2_bsh = blocks[1].return_bsh();
+
+        // a block file written before the header existed, cut short
+        let legacy_encoded = bincode::serialize(&blocks[1].body).unwrap();
+        let blk2_filename = storage.dest.join("blocks").join(format!("{}.sai", blocks[1].return_bsh_as_hex()));
+
+        for blk in blocks {
+            blockchain.add_block(blk, &mut shashmap, &storage);
+        }
+        std::fs::write(&blk2_filename, &legacy_encoded[..legacy_encoded.len() / 2]).unwrap();
+
+        match storage.read_block_from_disk(blk2_bsh) {
+            Err(StorageError::CorruptBlock(bsh, _)) => assert_eq!(bsh, blk2_bsh),
+            _ => panic!("expected the truncated legacy block to be corrupt"),
+        }
+
+        assert_eq!(blockchain.check_consistency(&mut shashmap, &storage).unwrap(), 1);
+        assert_eq!(blockchain.return_latest_block_header(), Some(blk1_header));
+        assert!(!blockchain.is_bsh_indexed(blk2_bsh));
+    }
+
+    #[test]
+    fn test_reorganization_with_missing_block() {
+        let (_, publickey) = generate_keys();
+
+        let mut blockchain = Blockchain::new();
+        let mut shashmap = Shashmap::new();
+        let storage = Storage::in_memory();
+        let other_storage = Storage::in_memory();
+
+        let mut blocks = create_chain(publickey, [0; 32], 1, 2, 1_000);
+        let mut fork = create_chain(publickey, blocks[0].return_bsh(), 2, 2, 2_000);
+
+        blocks[1].set_transactions(&mut vec![create_new_transaction(publickey, 2_000)]);
+
+        let slip = blocks[1].body.txs[0].return_from_slips()[0].clone();
+
+        let blk2_header = blocks[1].header();
+        let fork_blk2_bsh = fork[0].return_bsh();
+        let fork_blk3_bsh = fork[1].return_bsh();
+
+        for blk in blocks {
+            blockchain.add_block(blk, &mut shashmap, &storage);
+        }
+
+        // fork_blk2 is indexed, but never reaches our store
+        blockchain.add_block(fork.remove(0), &mut shashmap, &other_storage);
+        blockchain.add_block(fork.remove(0), &mut shashmap, &storage);
+
+        assert_eq!(blockchain.return_latest_block_header(), Some(blk2_header));
+        assert_eq!(blockchain.return_longest_chain_block_header_by_id(2).unwrap().bsh, blk2_header.bsh);
+        assert!(blockchain.return_longest_chain_block_header_by_id(3).is_none());
+        assert_eq!(shashmap.return_value(slip.return_signature_source()), Some(&2));
+        assert_eq!(blockchain.return_missing_blocks(), vec![fork_blk2_bsh]);
+
+        match blockchain.return_block_by_hash(fork_blk3_bsh, &other_storage) {
+            Err(StorageError::BlockNotFound(bsh)) => assert_eq!(bsh, fork_blk3_bsh),
+            _ => panic!("expected fork_blk3 to be missing from the other store"),
+        }
+    }
+
+    //
+    // a block store whose disk is full
+    //
+    struct FailingBlockStore;
+
+    impl BlockStore for FailingBlockStore {
+        fn write_block(&self, _blk: &Block, _batch: &mut StoreBatch) -> Result<(), StorageError> {
+            return Err(StorageError::Io(std::io::Error::new(std::io::ErrorKind::Other, "disk full")));
+        }
+
+        fn read_block(&self, bsh: [u8; 32]) -> Result<Block, StorageError> {
+            return Err(StorageError::BlockNotFound(bsh));
+        }
+
+        fn verify_blocks(&self, _report: &mut StorageReport) -> Result<(), StorageError> {
+            return Ok(());
+        }
+    }
+
+    #[test]
+    fn test_failed_block_write() {
+        let (_, publickey) = generate_keys();
+
+        let mut blockchain = Blockchain::new();
+        let mut shashmap = Shashmap::new();
+        let storage = Storage::in_memory();
+        let full_storage = Storage::with_block_store(Arc::new(FailingBlockStore));
+
+        let recorder = Arc::new(RwLock::new(ReorganizationRecorder { wound: vec![], unwound: vec![] }));
+        blockchain.register_subscriber(recorder.clone());
+
+        let mut blocks = create_chain(publickey, [0; 32], 1, 2, 1_000);
+        blocks[1].set_transactions(&mut vec![create_new_transaction(publickey, 2_000)]);
+
+        let slip = blocks[1].body.txs[0].return_from_slips()[0].clone();
+        let blk1_header = blocks[0].header();
+        let blk2_bsh = blocks[1].return_bsh();
+
+        blockchain.add_block(blocks.remove(0), &mut shashmap, &storage);
+        blockchain.add_block(blocks.remove(0), &mut shashmap, &full_storage);
+
+        assert_eq!(blockchain.return_latest_block_header(), Some(blk1_header.clone()));
+        assert!(!blockchain.is_bsh_indexed(blk2_bsh));
+        assert!(!blockchain.is_on_longest_chain(blk2_bsh));
+        assert_eq!(recorder.read().unwrap().wound, vec![blk1_header.bsh]);
+        assert!(recorder.read().unwrap().unwound.is_empty());
+        assert_ne!(shashmap.return_value(slip.return_signature_source()), Some(&2));
+    }
+
 }
@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::{Read, Write};
 use std::path::{PathBuf};
@@ -7,20 +8,149 @@ use serde::{Serialize, Deserialize};
 use saito_primitives::slip:: Slip;
 use saito_primitives::crypto::{ReadablePublicKey, ReadablePrivateKey};
 
+use crate::block_cache::DEFAULT_BLOCK_CACHE_SIZE;
+use crate::block_store::BlockStoreBackend;
+use crate::mempool::{
+    DEFAULT_MINIMUM_FEE,
+    DEFAULT_MAX_MEMPOOL_SIZE,
+    DEFAULT_MAX_MEMPOOL_TRANSACTIONS,
+    DEFAULT_MAX_TRANSACTION_AGE,
+    DEFAULT_MAX_QUEUED_BLOCKS,
+    TransactionPriority,
+};
+
 pub const TREASURY: u64 = 286_810_000_000_000_00;
 pub const GENESIS_PERIOD: u64 = 21500;
+pub const DEFAULT_MAX_REORG_DEPTH: u32 = 100;
+pub const MAX_BLOCK_SIZE: u64 = 10_000_000;
+pub const MAX_BLOCK_TRANSACTIONS: usize = 10_000;
+pub const DEFAULT_DATA_DIR: &str = "data";
 
 #[derive(Serialize, Deserialize)]
 pub struct Config {
     pub config_filename: String,
     pub chain_config: ChainConfig,
+    #[serde(default)]
+    pub storage_config: StorageConfig,
+    #[serde(default)]
+    pub mempool_config: MempoolConfig,
     pub wallet_config: WalletConfig,
     pub network_config: NetworkConfig,
 }
 
 
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct ChainConfig {
+    // hex-encoded block hash that must be at each block id
+    #[serde(default)]
+    pub checkpoints: HashMap<u32, String>,
+    // deepest reorganization we accept, 0 for no limit
+    #[serde(default = "default_max_reorg_depth")]
+    pub max_reorg_depth: u32,
+}
+
+impl Default for ChainConfig {
+    fn default() -> Self {
+        ChainConfig {
+            checkpoints: HashMap::new(),
+            max_reorg_depth: default_max_reorg_depth(),
+        }
+    }
+}
+
+fn default_max_reorg_depth() -> u32 {
+    return DEFAULT_MAX_REORG_DEPTH;
+}
+
 #[derive(Serialize, Deserialize)]
-pub struct ChainConfig {}
+pub struct StorageConfig {
+    // holds the blocks, index, wallet and peers directories
+    #[serde(default = "default_data_dir")]
+    pub data_dir: String,
+    // keep blocks in the RocksDB store or as files
+    #[serde(default)]
+    pub block_store: BlockStoreBackend,
+    // keep the optional index of slips by address
+    #[serde(default)]
+    pub index_addresses: bool,
+    // number of decoded blocks kept in memory
+    #[serde(default = "default_block_cache_size")]
+    pub block_cache_size: usize,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        StorageConfig {
+            data_dir: default_data_dir(),
+            block_store: BlockStoreBackend::default(),
+            index_addresses: false,
+            block_cache_size: default_block_cache_size(),
+        }
+    }
+}
+
+fn default_data_dir() -> String {
+    return String::from(DEFAULT_DATA_DIR);
+}
+
+fn default_block_cache_size() -> usize {
+    return DEFAULT_BLOCK_CACHE_SIZE;
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct MempoolConfig {
+    // smallest fee a transaction must pay to be accepted
+    #[serde(default = "default_minimum_fee")]
+    pub minimum_fee: u64,
+    // rank transactions for blocks by fee per byte or routing work
+    #[serde(default)]
+    pub priority: TransactionPriority,
+    // bytes of transactions held before the cheapest are evicted
+    #[serde(default = "default_max_mempool_size")]
+    pub max_size: u64,
+    // number of transactions held before the cheapest are evicted
+    #[serde(default = "default_max_mempool_transactions")]
+    pub max_transactions: usize,
+    // milliseconds a transaction may wait before it expires
+    #[serde(default = "default_max_transaction_age")]
+    pub max_transaction_age: u64,
+    // blocks from peers waiting to be added to the chain
+    #[serde(default = "default_max_queued_blocks")]
+    pub max_queued_blocks: usize,
+}
+
+impl Default for MempoolConfig {
+    fn default() -> Self {
+        MempoolConfig {
+            minimum_fee: default_minimum_fee(),
+            priority: TransactionPriority::default(),
+            max_size: default_max_mempool_size(),
+            max_transactions: default_max_mempool_transactions(),
+            max_transaction_age: default_max_transaction_age(),
+            max_queued_blocks: default_max_queued_blocks(),
+        }
+    }
+}
+
+fn default_minimum_fee() -> u64 {
+    return DEFAULT_MINIMUM_FEE;
+}
+
+fn default_max_mempool_size() -> u64 {
+    return DEFAULT_MAX_MEMPOOL_SIZE;
+}
+
+fn default_max_mempool_transactions() -> usize {
+    return DEFAULT_MAX_MEMPOOL_TRANSACTIONS;
+}
+
+fn default_max_transaction_age() -> u64 {
+    return DEFAULT_MAX_TRANSACTION_AGE;
+}
+
+fn default_max_queued_blocks() -> usize {
+    return DEFAULT_MAX_QUEUED_BLOCKS;
+}
 
 #[derive(Serialize, Deserialize)]
 pub struct WalletConfig {
@@ -1,24 +1,38 @@
 use std::{time};
 use std::sync::{Arc, RwLock};
 
+use crate::address_index::AddressIndex;
 use crate::blockchain::Blockchain;
+use crate::config::{MempoolConfig, GENESIS_PERIOD};
 use crate::mempool::Mempool;
+use crate::runtime::Runtime;
 use crate::wallet::Wallet;
 use crate::shashmap::Shashmap;
 use crate::network::NetworkMessage;
-use crate::types::BlockMessage;
+use crate::types::{AddressInfoRequest, BlockMessage, ChainConfigRequest, MempoolInfoRequest, ShutdownRequest};
 use crate::storage::Storage;
 
-// use saito_primitives::block::Block;
+use saito_primitives::block::Block;
+use saito_primitives::helper::create_timestamp;
+use saito_primitives::slip::Slip;
 
 use actix::*;
+use data_encoding::HEXLOWER;
+
+//
+// how often the pending transactions are saved, in milliseconds
+//
+const MEMPOOL_SAVE_INTERVAL: u64 = 60_000;
 
 #[derive(Clone)]
 pub struct Consensus {
     blockchain: Blockchain,
-    mempool:    Mempool,
+    mempool:    Arc<RwLock<Mempool>>,
     pub wallet: Arc<RwLock<Wallet>>,
     shashmap:   Shashmap,
+    storage:    Storage,
+    processing_blocks: bool,
+    address_index: Option<Arc<RwLock<AddressIndex>>>,
     pub lottery_addr: Recipient<BlockMessage>,
 }
 
@@ -30,6 +44,13 @@ impl Actor for Consensus {
 
     fn started(&mut self, ctx: &mut Self::Context) {
         self.heartbeat(ctx);
+        ctx.run_interval(time::Duration::from_millis(MEMPOOL_SAVE_INTERVAL), |act, _| {
+            act.save_mempool();
+        });
+    }
+
+    fn stopped(&mut self, _: &mut Self::Context) {
+        self.save_mempool();
     }
 }
 
@@ -40,43 +61,231 @@ impl Actor for Consensus {
 //
 impl Handler<NetworkMessage> for Consensus {
     type Result = ();
-    fn handle(&mut self, msg: NetworkMessage, _: &mut Context<Self>) {
+    fn handle(&mut self, msg: NetworkMessage, ctx: &mut Context<Self>) {
         match msg {
             NetworkMessage::IncomingBlock(blk) => {
-                self.blockchain.add_block(blk, &self.wallet, &mut self.shashmap);
+                self.queue_block(blk, ctx);
             },
             NetworkMessage::IncomingTransaction(tx) => {
-                self.mempool.add_transaction(tx);
+                let tx_hash = tx.return_hash();
+                match self.mempool.write().unwrap().add_transaction(tx, &self.shashmap) {
+                    Ok(()) => {},
+                    Err(err) => {
+                        println!("rejected transaction {}: {}", HEXLOWER.encode(&tx_hash), err);
+                        return;
+                    }
+                }
+                self.notify_evicted_transactions();
                 self.try_bundle();
             },
         }
     }
 }
 
+//
+// MEMPOOL QUERIES
+//
+impl Handler<MempoolInfoRequest> for Consensus {
+    type Result = MessageResult<MempoolInfoRequest>;
+    fn handle(&mut self, _: MempoolInfoRequest, _: &mut Context<Self>) -> Self::Result {
+        let latest_block_header = self.blockchain.return_latest_block_header();
+        return MessageResult(self.mempool.read().unwrap().return_info(latest_block_header, create_timestamp()));
+    }
+}
+
+//
+// ADDRESS QUERIES
+//
+impl Handler<AddressInfoRequest> for Consensus {
+    type Result = MessageResult<AddressInfoRequest>;
+    fn handle(&mut self, msg: AddressInfoRequest, _: &mut Context<Self>) -> Self::Result {
+        return MessageResult(self.address_index
+            .as_ref()
+            .map(|address_index| address_index.read().unwrap().return_info(&msg.publickey)));
+    }
+}
+
+//
+// CHAIN CONFIG
+//
+impl Handler<ChainConfigRequest> for Consensus {
+    type Result = MessageResult<ChainConfigRequest>;
+    fn handle(&mut self, msg: ChainConfigRequest, _: &mut Context<Self>) -> Self::Result {
+        match msg {
+            ChainConfigRequest::Return => {},
+            ChainConfigRequest::AddCheckpoint(bid, bsh) => self.blockchain.add_checkpoint(bid, bsh),
+            ChainConfigRequest::RemoveCheckpoint(bid) => { self.blockchain.remove_checkpoint(bid); },
+            ChainConfigRequest::SetMaxReorgDepth(max_reorg_depth) => self.blockchain.set_max_reorg_depth(max_reorg_depth),
+        }
+        return MessageResult(self.blockchain.return_chain_config());
+    }
+}
+
+//
+// SHUTDOWN
+//
+// the actors are dropped without being stopped once the system
+// stops, so the mempool is saved here rather than left to stopped
+//
+impl Handler<ShutdownRequest> for Consensus {
+    type Result = ();
+    fn handle(&mut self, _msg: ShutdownRequest, _: &mut Context<Self>) {
+        self.save_mempool();
+        System::current().stop();
+    }
+}
+
 impl Consensus {
-    pub fn new(wallet: Arc<RwLock<Wallet>>, lottery_addr: Recipient<BlockMessage>) -> Consensus {
+    //
+    // the blockchain and shashmap are restored, checked and
+    // configured before we start (see restore_blockchain in main)
+    //
+    pub fn new(
+        wallet: Arc<RwLock<Wallet>>,
+        runtime: Arc<RwLock<Runtime>>,
+        storage: Storage,
+        mut blockchain: Blockchain,
+        shashmap: Shashmap,
+        lottery_addr: Recipient<BlockMessage>
+    ) -> Consensus {
+        let mempool = Arc::new(RwLock::new(Mempool::new()));
+        mempool.write().unwrap().set_publickey(&wallet.read().unwrap().return_publickey());
+        mempool.write().unwrap().set_latest_block_hash(blockchain.return_latest_block_header().map(|block_header| block_header.bsh));
+
+        //
+        // wallet, mempool and runtime track the longest chain
+        //
+        blockchain.register_subscriber(wallet.clone());
+        blockchain.register_subscriber(mempool.clone());
+        blockchain.register_subscriber(runtime);
+
         return Consensus {
-            blockchain: Blockchain::new(),
-            mempool:    Mempool::new(),
-            shashmap:   Shashmap::new(),
+            blockchain,
+            mempool,
+            shashmap,
+            storage,
+            processing_blocks: false,
+            address_index: None,
             wallet,
             lottery_addr
         }
     }
 
+    //
+    // the address index is optional, as it adds storage writes for
+    // every slip on the longest chain. it only indexes blocks added
+    // to the longest chain after it is enabled, and is queried with
+    // an AddressInfoRequest.
+    //
+    pub fn enable_address_index(&mut self) -> Arc<RwLock<AddressIndex>> {
+        if let Some(address_index) = &self.address_index {
+            return address_index.clone();
+        }
+
+        let address_index = Arc::new(RwLock::new(AddressIndex::new(self.storage.clone())));
+        self.blockchain.register_subscriber(address_index.clone());
+        self.address_index = Some(address_index.clone());
+
+        return address_index;
+    }
+
+    //
+    // let back in the transactions that were waiting when we last
+    // stopped. each one is checked again against the restored
+    // shashmap, so those that were spent or went stale meanwhile are
+    // left out.
+    //
+    pub fn restore_mempool(&mut self) {
+        let saved_transactions = match self.storage.read_mempool_from_disk() {
+            Ok(saved_transactions) => saved_transactions,
+            Err(err) => {
+                println!("could not restore mempool: {}", err);
+                return;
+            }
+        };
+
+        let saved_count = saved_transactions.len();
+        let mut restored_count = 0;
+        {
+            let mut mempool = self.mempool.write().unwrap();
+            for tx in saved_transactions {
+                if mempool.add_transaction(tx, &self.shashmap).is_ok() {
+                    restored_count += 1;
+                }
+            }
+        }
+
+        if saved_count > 0 {
+            println!("restored {} of {} saved mempool transactions", restored_count, saved_count);
+        }
+    }
+
+    pub fn save_mempool(&self) {
+        let pending_transactions = self.mempool.read().unwrap().return_pending_transactions();
+        if let Err(err) = self.storage.write_mempool_to_disk(&pending_transactions) {
+            println!("could not save mempool: {}", err);
+        }
+    }
+
+    pub fn configure_mempool(&mut self, mempool_config: &MempoolConfig) {
+        let mut mempool = self.mempool.write().unwrap();
+        mempool.set_minimum_fee(mempool_config.minimum_fee);
+        mempool.set_priority(mempool_config.priority);
+        mempool.set_limits(mempool_config.max_size, mempool_config.max_transactions, mempool_config.max_transaction_age);
+        mempool.set_max_queued_blocks(mempool_config.max_queued_blocks);
+    }
+
     pub fn heartbeat(&mut self, ctx: &mut Context<Self>) {
         ctx.run_later(time::Duration::from_millis(1000), |act, ctx| {
+            act.expire_transactions();
             act.try_bundle();
             act.heartbeat(ctx);
         });
     }
 
+    //
+    // once the chain is longer than the genesis period, transactions
+    // made before the block a genesis period behind the tip could
+    // only spend slips that have fallen out of the chain
+    //
+    pub fn expire_transactions(&mut self) {
+        let genesis_ts = self.blockchain.return_genesis_boundary_ts(GENESIS_PERIOD);
+        let expired = self.mempool.write().unwrap().expire_transactions(create_timestamp(), genesis_ts);
+        if expired > 0 {
+            println!("expired {} transactions from the mempool", expired);
+        }
+        self.notify_evicted_transactions();
+    }
+
+    //
+    // tell the wallet when one of its transactions was dropped from
+    // the mempool, so it can spend the inputs it had set aside. an
+    // input that a replacement transaction still spends stays spent.
+    //
+    pub fn notify_evicted_transactions(&mut self) {
+        let publickey = self.wallet.read().unwrap().return_publickey();
+        let mut mempool = self.mempool.write().unwrap();
+
+        for tx in mempool.return_evicted_transactions() {
+            let released_inputs: Vec<Slip> = tx.return_from_slips()
+                .into_iter()
+                .filter(|slip| slip.return_add() == publickey && !mempool.is_input_pending(slip))
+                .collect();
+
+            if tx.return_from_slips().iter().any(|slip| slip.return_add() == publickey) {
+                println!("our transaction {} was dropped from the mempool", HEXLOWER.encode(&tx.return_hash()));
+                self.wallet.write().unwrap().release_inputs(&released_inputs);
+            }
+        }
+    }
+
     pub fn try_bundle(&mut self) {
         let last_block_header = self.blockchain.return_latest_block_header();
 
         // possibly pass by reference?
-        if self.mempool.can_bundle_block(last_block_header.clone()) {
-            let mut blk = self.mempool.bundle_block(&self.wallet, last_block_header);
+        let can_bundle_block = self.mempool.write().unwrap().can_bundle_block(last_block_header.clone());
+        if can_bundle_block {
+            let mut blk = self.mempool.write().unwrap().bundle_block(&self.wallet, last_block_header);
             
             let reclaimed_funds = self.blockchain.calculate_reclaimed_funds(blk.header());
             blk.set_reclaimed(reclaimed_funds);
@@ -84,14 +293,131 @@ impl Consensus {
             println!("BLOCK : {:?}", blk);
  
             // need to add some control flow if a block isn't produced successfully
-            self.blockchain.add_block(blk, &mut self.wallet, &mut self.shashmap);
+            self.add_block(blk);
 
             let block_header = self.blockchain.return_latest_block_header();
-            let block_message = BlockMessage::new(Storage::read_block_from_disk(block_header.unwrap().bsh));
+            match self.storage.read_block_from_disk(block_header.unwrap().bsh) {
+                Ok(latest_blk) => {
+                    // send the latest block to the lottery to start the next game 
+                    self.lottery_addr.do_send(BlockMessage::new(latest_blk)).unwrap();
+                },
+                Err(err) => println!("could not load latest block for the lottery: {}", err),
+            }
+        }
+
+    }
+
+    //
+    // blocks from the network are queued in the mempool and added
+    // one at a time, so a flood of blocks does not keep the actor
+    // from handling other messages in between
+    //
+    pub fn queue_block(&mut self, blk: Block, ctx: &mut Context<Self>) {
+        let bsh = blk.return_bsh();
+        if self.blockchain.is_bsh_indexed(bsh) { return; }
 
-            // send the latest block to the lottery to start the next game 
-            self.lottery_addr.do_send(block_message).unwrap();
+        if let Err(err) = self.mempool.write().unwrap().add_block(blk) {
+            println!("dropped block {}: {}", HEXLOWER.encode(&bsh), err);
+            return;
         }
 
+        if !self.processing_blocks {
+            self.processing_blocks = true;
+            self.process_next_block(ctx);
+        }
+    }
+
+    fn process_next_block(&mut self, ctx: &mut Context<Self>) {
+        ctx.run_later(time::Duration::from_millis(0), |act, ctx| {
+            let next_block = act.mempool.write().unwrap().return_next_block();
+            match next_block {
+                Some(blk) => {
+                    if !act.blockchain.is_bsh_indexed(blk.return_bsh()) {
+                        act.add_block(blk);
+                    }
+                    act.process_next_block(ctx);
+                },
+                None => act.processing_blocks = false,
+            }
+        });
+    }
+
+    //
+    // add a block to the blockchain and recover any transactions
+    // that were unwound in the process. the blockchain saves the
+    // block and the state it leaves us in as it adds it.
+    //
+    pub fn add_block(&mut self, blk: Block) {
+        self.blockchain.add_block(blk, &mut self.shashmap, &self.storage);
+        self.mempool.write().unwrap().recover_transactions(&self.shashmap);
+        self.notify_evicted_transactions();
+
+        //
+        // TODO
+        //
+        // send these requests to our peers once the network can
+        // fetch blocks on our behalf
+        //
+        for bsh in self.blockchain.return_missing_blocks() {
+            println!("missing block: {:?}", bsh);
+        }
+
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use saito_primitives::transaction::Transaction;
+
+    struct BlockSink;
+
+    impl Actor for BlockSink {
+        type Context = Context<Self>;
+    }
+
+    impl Handler<BlockMessage> for BlockSink {
+        type Result = ();
+        fn handle(&mut self, _msg: BlockMessage, _: &mut Context<Self>) {}
+    }
+
+    #[test]
+    fn test_mempool_saved_on_shutdown() {
+        let wallet = Arc::new(RwLock::new(Wallet::new()));
+        let publickey = wallet.read().unwrap().return_publickey();
+
+        let mut input = Slip::new(publickey);
+        input.set_amt(10_000);
+        input.set_ids(1, 1, 0);
+        let mut shashmap = Shashmap::new();
+        shashmap.unspend_slip(&input, 1);
+
+        let mut tx = Transaction::new();
+        tx.add_from_slip(input);
+        let mut output = Slip::new(publickey);
+        output.set_amt(9_500);
+        tx.add_to_slip(output);
+        tx.set_sig(wallet.read().unwrap().create_signature(&tx.return_signature_source()));
+        let tx_hash = tx.return_hash();
+
+        let storage = Storage::in_memory();
+        let consensus_storage = storage.clone();
+
+        let system = System::new("test_mempool_saved_on_shutdown");
+        let consensus_addr = Consensus::create(move |_| {
+            let lottery_addr = BlockSink.start().recipient();
+            let runtime = Arc::new(RwLock::new(Runtime::new()));
+            let consensus = Consensus::new(wallet, runtime, consensus_storage, Blockchain::new(), shashmap, lottery_addr);
+            consensus.mempool.write().unwrap().add_transaction(tx, &consensus.shashmap).unwrap();
+            return consensus;
+        });
+
+        consensus_addr.do_send(ShutdownRequest);
+        system.run().unwrap();
+
+        let saved_transactions = storage.read_mempool_from_disk().unwrap();
+        assert_eq!(saved_transactions.len(), 1);
+        assert_eq!(saved_transactions[0].return_hash(), tx_hash);
     }
 }
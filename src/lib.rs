@@ -1,5 +1,11 @@
+pub mod address_index;
+pub mod archive;
+pub mod block_cache;
+pub mod block_file;
+pub mod block_store;
 pub mod blockchain;
 pub mod storage;
+pub mod store;
 pub mod lottery;
 pub mod config;
 pub mod mempool;
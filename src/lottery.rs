@@ -81,7 +81,11 @@ impl LotteryGame for Miner {
                     0
                 ) {
                     Some(tx) => tx,
-                    None => Transaction::new(),
+                    None => {
+                        let mut tx = Transaction::new();
+                        tx.set_tx_type(TransactionBroadcastType::GoldenTicket);
+                        tx
+                    },
                 };
                 
                 let mut miner_slip = Slip::new(publickey);
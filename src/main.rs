@@ -3,7 +3,16 @@ use saito_core::consensus::Consensus;
 use saito_core::runtime::Runtime;
 use saito_core::wallet::Wallet;
 use saito_core::lottery::{Lottery, Miner};
+use saito_core::storage::Storage;
+use saito_core::archive::{export_chain, import_chain, ArchiveProgress};
+use saito_core::blockchain::Blockchain;
+use saito_core::shashmap::Shashmap;
+use saito_core::config::{Config, ChainConfig, StorageConfig, MempoolConfig};
+use saito_core::types::ShutdownRequest;
+use std::env;
 use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::process;
 use std::sync::{Arc, RwLock};
 
 use actix::*;
@@ -12,6 +21,44 @@ fn main() {
 
     println!("start main");
 
+    //
+    // usage: saito [verify-storage | export-chain <file> | import-chain <file>] [--config <file>]
+    //
+    let args: Vec<String> = env::args().collect();
+    let (chain_config, storage_config, mempool_config) = match args.iter().position(|arg| arg == "--config") {
+        Some(i) if i + 1 < args.len() => {
+            let config = Config::read_from_file(&PathBuf::from(&args[i + 1]));
+            (config.chain_config, config.storage_config, config.mempool_config)
+        },
+        _ => (ChainConfig::default(), StorageConfig::default(), MempoolConfig::default()),
+    };
+
+    let storage = match Storage::open_with_backend(&storage_config.data_dir, storage_config.block_store) {
+        Ok(storage) => storage,
+        Err(err) => {
+            println!("could not open data directory {}: {}", storage_config.data_dir, err);
+            process::exit(1);
+        }
+    };
+
+    storage.set_block_cache_capacity(storage_config.block_cache_size);
+
+    if args.iter().any(|arg| arg == "verify-storage") {
+        verify_storage(&storage);
+        return;
+    }
+
+    if let Some(i) = args.iter().position(|arg| arg == "export-chain" || arg == "import-chain") {
+        if i + 1 >= args.len() {
+            println!("{} needs the path of the archive", args[i]);
+            process::exit(1);
+        }
+        chain_archive(&storage, &chain_config, &args[i], Path::new(&args[i + 1]));
+        return;
+    }
+
+    let (blockchain, shashmap) = restore_blockchain(&storage, &chain_config);
+
     //
     // Actix framework allows communications between different
     // parts of the system. There are three major sections that
@@ -26,7 +73,7 @@ fn main() {
     //    
     // Instantiate
     //
-    Consensus::create(|ctx| {
+    let consensus_addr = Consensus::create(move |ctx| {
         let consensus_addr = ctx.address().recipient();
         
         // need to add config in here
@@ -35,12 +82,25 @@ fn main() {
         let lottery = Lottery::new(Miner::new(), wallet.clone(), consensus_addr.clone());
         let lottery_addr = lottery.start().recipient();
         
-        let _runtime = Runtime::new();
+        let runtime = Arc::new(RwLock::new(Runtime::new()));
         let _network = Network { consensus_addr: consensus_addr.clone() };
 
-        return Consensus::new(wallet.clone(), lottery_addr);
+        let mut consensus = Consensus::new(wallet.clone(), runtime, storage, blockchain, shashmap, lottery_addr);
+        consensus.configure_mempool(&mempool_config);
+        consensus.restore_mempool();
+        if storage_config.index_addresses {
+            consensus.enable_address_index();
+        }
+
+        return consensus;
     });
 
+    //
+    // save the mempool before exiting on SIGINT or SIGTERM
+    //
+    ctrlc::set_handler(move || consensus_addr.do_send(ShutdownRequest))
+        .expect("could not install the shutdown handler");
+
 
     //
     // run the part of the code that handles the exchange of 
@@ -51,5 +111,94 @@ fn main() {
 
 }
 
+//
+// check every stored block against its checksum and report the
+// ones that are corrupt, exiting with an error if there are any
+//
+fn verify_storage(storage: &Storage) {
+    match storage.verify_storage() {
+        Ok(report) => {
+            println!("verified {} blocks", report.verified);
+            for filename in report.legacy.iter() {
+                println!("legacy block file (no checksum): {}", filename);
+            }
+            for (filename, err) in report.corrupt.iter() {
+                println!("corrupt block: {} -- {}", filename, err);
+            }
+            if report.corrupt.len() > 0 { process::exit(1); }
+        },
+        Err(err) => {
+            println!("could not verify storage: {}", err);
+            process::exit(1);
+        }
+    }
+}
+
+//
+// restore the blockchain and shashmap from the last time we were
+// running, or start with an empty chain if nothing was saved. we
+// exit rather than start over if the saved state cannot be read,
+// so that it is never replaced by an empty chain.
+//
+fn restore_blockchain(storage: &Storage, chain_config: &ChainConfig) -> (Blockchain, Shashmap) {
+    let (mut blockchain, mut shashmap) = match storage.read_blockchain_from_disk() {
+        Ok(Some(saved_state)) => saved_state,
+        Ok(None) => (Blockchain::new(), Shashmap::new()),
+        Err(err) => {
+            println!("could not restore blockchain: {}", err);
+            process::exit(1);
+        }
+    };
+
+    //
+    // make sure the chain we restored only refers to blocks that
+    // we are able to read
+    //
+    match blockchain.check_consistency(&mut shashmap, storage) {
+        Ok(0) => {},
+        Ok(_) => {
+            if let Err(err) = storage.write_blockchain_to_disk(&blockchain, &mut shashmap) {
+                println!("could not save blockchain: {}", err);
+                process::exit(1);
+            }
+        },
+        Err(err) => {
+            println!("could not check blockchain consistency: {}", err);
+            process::exit(1);
+        }
+    }
 
+    blockchain.apply_chain_config(chain_config);
 
+    return (blockchain, shashmap);
+}
+
+//
+// write the longest chain out to a bootstrap archive, or load one
+// into our chain, reporting progress as we go
+//
+fn chain_archive(storage: &Storage, chain_config: &ChainConfig, command: &str, path: &Path) {
+    let (mut blockchain, mut shashmap) = restore_blockchain(storage, chain_config);
+
+    let mut report_progress = |archive_progress: &ArchiveProgress| {
+        print!("\r{} blocks, {} / {} bytes", archive_progress.blocks, archive_progress.bytes, archive_progress.total_bytes);
+        std::io::stdout().flush().unwrap();
+    };
+
+    let result = if command == "export-chain" {
+        export_chain(&blockchain, storage, path, &mut report_progress)
+    } else {
+        import_chain(&mut blockchain, &mut shashmap, storage, path, &mut report_progress)
+    };
+    println!("");
+
+    match result {
+        Ok(archive_progress) => {
+            println!("{} blocks, {} already in our chain", archive_progress.blocks, archive_progress.skipped);
+        },
+        Err(err) => {
+            println!("{} failed: {}", command, err);
+            process::exit(1);
+        }
+    }
+}
@@ -1,44 +1,943 @@
 // use std::{thread, time};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
 use std::sync::RwLock;
 use crate::wallet::Wallet;
-use crate::config::GENESIS_PERIOD;
+use crate::config::{GENESIS_PERIOD, MAX_BLOCK_SIZE, MAX_BLOCK_TRANSACTIONS};
+use crate::blockchain::ChainReorganization;
+use crate::shashmap::Shashmap;
 
 use saito_primitives::block::{Block, BlockHeader};
 use saito_primitives::burnfee::BurnFee;
+use saito_primitives::crypto::PublicKey;
+use saito_primitives::slip::Slip;
 use saito_primitives::transaction::{Transaction, TransactionBroadcastType};
 use saito_primitives::golden_ticket::GoldenTicket;
 use saito_primitives::helper::create_timestamp;
 
+use serde::{Serialize, Deserialize};
+
+pub const DEFAULT_MINIMUM_FEE: u64 = 0;
+pub const DEFAULT_MAX_MEMPOOL_SIZE: u64 = 100_000_000;
+pub const DEFAULT_MAX_MEMPOOL_TRANSACTIONS: usize = 100_000;
+pub const DEFAULT_MAX_TRANSACTION_AGE: u64 = 86_400_000;
+pub const DEFAULT_MAX_QUEUED_BLOCKS: usize = 1_000;
+pub const MAX_TRANSACTION_CLOCK_DRIFT: u64 = 60_000;
+
+//
+// why a transaction was turned away from the mempool, which is
+// passed back to whoever submitted it
+//
+#[derive(PartialEq, Debug, Clone)]
+pub enum MempoolError {
+    Duplicate,
+    Malformed(&'static str),
+    InvalidSignature,
+    InputUnavailable,
+    FeeTooLow { fee: u64, minimum: u64 },
+    ReplacementFeeTooLow { fee: u64, replaced_fee: u64 },
+    StaleGoldenTicket,
+    GoldenTicketExists,
+    MempoolFull,
+    FutureTimestamp { ts: u64, now: u64 },
+}
+
+impl fmt::Display for MempoolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MempoolError::Duplicate => write!(f, "transaction is already in the mempool"),
+            MempoolError::Malformed(reason) => write!(f, "malformed transaction: {}", reason),
+            MempoolError::InvalidSignature => write!(f, "transaction signature is not valid"),
+            MempoolError::InputUnavailable => write!(f, "transaction spends a slip that does not exist or is already spent"),
+            MempoolError::FeeTooLow { fee, minimum } => write!(f, "transaction fee {} is below the minimum of {}", fee, minimum),
+            MempoolError::ReplacementFeeTooLow { fee, replaced_fee } => write!(f, "transaction fee {} does not beat the {} paid by the transactions it conflicts with", fee, replaced_fee),
+            MempoolError::StaleGoldenTicket => write!(f, "golden ticket does not solve the latest block"),
+            MempoolError::GoldenTicketExists => write!(f, "mempool already holds a golden ticket for this block"),
+            MempoolError::MempoolFull => write!(f, "mempool is full of transactions paying a higher fee"),
+            MempoolError::FutureTimestamp { ts, now } => write!(f, "transaction timestamp {} is ahead of our clock at {}", ts, now),
+        }
+    }
+}
+
+impl Error for MempoolError {}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum BlockQueueError {
+    Duplicate,
+    QueueFull,
+}
+
+impl fmt::Display for BlockQueueError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BlockQueueError::Duplicate => write!(f, "block is already queued"),
+            BlockQueueError::QueueFull => write!(f, "block queue is full"),
+        }
+    }
+}
+
+impl Error for BlockQueueError {}
+
+//
+// how transactions are ranked when a block is put together
+//
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+pub enum TransactionPriority {
+    FeePerByte,
+    RoutingWork,
+}
+
+impl Default for TransactionPriority {
+    fn default() -> Self {
+        TransactionPriority::FeePerByte
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub struct MempoolStats {
+    pub transactions: usize,
+    pub size:         u64,
+    pub evicted:      u64,	// pushed out by transactions paying more
+    pub replaced:     u64,	// spent the same slips as a transaction paying more
+    pub expired:      u64,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct MempoolTransactionInfo {
+    pub tx_hash:      [u8; 32],
+    pub tx_type:      TransactionBroadcastType,
+    pub fee:          u64,
+    pub size:         u64,
+    pub routing_work: u64,
+}
+
+//
+// a snapshot of the mempool for anything that wants to look in
+//
+#[derive(PartialEq, Debug, Clone)]
+pub struct MempoolInfo {
+    pub stats:           MempoolStats,
+    pub total_fees:      u64,
+    pub golden_tickets:  usize,
+    pub queued_blocks:   usize,
+    pub work_available:  u64,
+    pub work_needed:     u64,	// to produce a block right now
+    pub time_until_block: u64,	// milliseconds until our work covers what is needed
+    pub transactions:    Vec<MempoolTransactionInfo>,
+}
+
+//
+// fee per byte, compared by cross multiplying so that small
+// differences are not lost to rounding
+//
+#[derive(Debug, Clone, Copy)]
+struct FeePerByte {
+    fee:  u64,
+    size: u64,
+}
+
+impl Ord for FeePerByte {
+    fn cmp(&self, other: &FeePerByte) -> Ordering {
+        let self_value = self.fee as u128 * other.size as u128;
+        let other_value = other.fee as u128 * self.size as u128;
+        return self_value.cmp(&other_value);
+    }
+}
+
+impl PartialOrd for FeePerByte {
+    fn partial_cmp(&self, other: &FeePerByte) -> Option<Ordering> {
+        return Some(self.cmp(other));
+    }
+}
+
+impl PartialEq for FeePerByte {
+    fn eq(&self, other: &FeePerByte) -> bool {
+        return self.cmp(other) == Ordering::Equal;
+    }
+}
+
+impl Eq for FeePerByte {}
+
+//
+// a pending transaction along with the values that ranking and
+// eviction look at, which are worked out once when it comes in
+//
+#[derive(Debug, Clone)]
+struct MempoolEntry {
+    tx:           Transaction,
+    tx_hash:      [u8; 32],
+    sequence:     u64,		// order in which it was let in
+    size:         u64,
+    fee:          u64,
+    routing_work: u64,
+}
+
+impl MempoolEntry {
+    fn new(tx: Transaction, routing_work: u64) -> MempoolEntry {
+        return MempoolEntry {
+            tx_hash:      tx.return_hash(),
+            sequence:     0,
+            size:         bincode::serialized_size(&tx).unwrap(),
+            fee:          tx.return_fees_total(),
+            routing_work,
+            tx,
+        };
+    }
+
+    fn return_fee_per_byte(&self) -> FeePerByte {
+        return FeePerByte { fee: self.fee, size: self.size };
+    }
+
+    fn return_eviction_key(&self) -> (FeePerByte, u64, [u8; 32]) {
+        return (self.return_fee_per_byte(), self.sequence, self.tx_hash);
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Mempool {
-    blocks: Vec<Block>,
-    pub transactions: Vec<Transaction>,
+    queued_blocks: BTreeMap<(u32, [u8; 32]), Block>,	// blocks from peers by id and bsh
+    max_queued_blocks: usize,
+    transactions: HashMap<[u8; 32], MempoolEntry>,	// pending transactions by hash
+    admission_order: BTreeMap<u64, [u8; 32]>,		// tx hashes by sequence
+    eviction_order: BTreeSet<(FeePerByte, u64, [u8; 32])>,	// lowest fee per byte first
+    next_sequence: u64,
+    transaction_outputs: HashMap<Vec<u8>, [u8; 32]>,	// tx hash by output slip
+    transaction_inputs: HashMap<Vec<u8>, [u8; 32]>,	// tx hash by input slip
+    evicted_transactions: Vec<Transaction>,		// removed without making it into a block
+    golden_tickets: HashMap<[u8; 32], Transaction>,	// golden ticket by the bsh it solves
+    latest_bsh: Option<[u8; 32]>,
+    recovered_transactions: Vec<Transaction>,
     burnfee: BurnFee,
-    work_available: u64,
+    work_available: u64,	// routing work of every transaction in the mempool
+    size: u64,			// bytes of every transaction in the mempool
+    minimum_fee: u64,
+    priority: TransactionPriority,
+    publickey: String,
+    max_size: u64,
+    max_transactions: usize,
+    max_transaction_age: u64,
+    evicted: u64,
+    replaced: u64,
+    expired: u64,
 }
 
 impl Mempool {
     pub fn new() -> Mempool {
         return Mempool {
-            blocks: vec![],
-            transactions: vec![],
+            queued_blocks: BTreeMap::new(),
+            max_queued_blocks: DEFAULT_MAX_QUEUED_BLOCKS,
+            transactions: HashMap::new(),
+            admission_order: BTreeMap::new(),
+            eviction_order: BTreeSet::new(),
+            next_sequence: 0,
+            transaction_outputs: HashMap::new(),
+            transaction_inputs: HashMap::new(),
+            evicted_transactions: vec![],
+            golden_tickets: HashMap::new(),
+            latest_bsh: None,
+            recovered_transactions: vec![],
             burnfee: BurnFee::new(0.0, 0),
             work_available: 0,
+            size: 0,
+            minimum_fee: DEFAULT_MINIMUM_FEE,
+            priority: TransactionPriority::default(),
+            publickey: String::new(),
+            max_size: DEFAULT_MAX_MEMPOOL_SIZE,
+            max_transactions: DEFAULT_MAX_MEMPOOL_TRANSACTIONS,
+            max_transaction_age: DEFAULT_MAX_TRANSACTION_AGE,
+            evicted: 0,
+            replaced: 0,
+            expired: 0,
+        };
+    }
+
+    //
+    // routing work is measured for the node that owns the mempool
+    //
+    pub fn set_publickey(&mut self, publickey: &PublicKey) {
+        self.publickey = publickey.to_string();
+    }
+
+    pub fn set_minimum_fee(&mut self, minimum_fee: u64) {
+        self.minimum_fee = minimum_fee;
+    }
+
+    pub fn set_priority(&mut self, priority: TransactionPriority) {
+        self.priority = priority;
+    }
+
+    pub fn set_limits(&mut self, max_size: u64, max_transactions: usize, max_transaction_age: u64) {
+        self.max_size = max_size;
+        self.max_transactions = max_transactions;
+        self.max_transaction_age = max_transaction_age;
+    }
+
+    //
+    // golden tickets are pending transactions like any other, so
+    // they are counted here as they are in return_info
+    //
+    pub fn return_stats(&self) -> MempoolStats {
+        let golden_ticket_size: u64 = self.golden_tickets
+            .values()
+            .map(|golden_ticket| bincode::serialized_size(golden_ticket).unwrap())
+            .sum();
+
+        return MempoolStats {
+            transactions: self.transactions.len() + self.golden_tickets.len(),
+            size:         self.size + golden_ticket_size,
+            evicted:      self.evicted,
+            replaced:     self.replaced,
+            expired:      self.expired,
+        };
+    }
+
+    //
+    // the tip of the longest chain, which the golden tickets we
+    // hold have to solve. it follows the chain reorganizations after
+    // this.
+    //
+    pub fn set_latest_block_hash(&mut self, latest_bsh: Option<[u8; 32]>) {
+        self.latest_bsh = latest_bsh;
+        self.drop_stale_golden_tickets();
+    }
+
+    pub fn set_max_queued_blocks(&mut self, max_queued_blocks: usize) {
+        self.max_queued_blocks = max_queued_blocks;
+    }
+
+    //
+    // blocks from peers wait here until the blockchain is ready for
+    // them. when the queue is full, a block closer to our chain
+    // pushes out the queued block with the highest id.
+    //
+    pub fn add_block(&mut self, blk: Block) -> Result<(), BlockQueueError> {
+        let key = (blk.body.id, blk.return_bsh());
+        if self.queued_blocks.contains_key(&key) {
+            return Err(BlockQueueError::Duplicate);
+        }
+
+        if self.queued_blocks.len() >= self.max_queued_blocks {
+            let highest_key = match self.queued_blocks.keys().next_back() {
+                Some(highest_key) if *highest_key > key => *highest_key,
+                _ => return Err(BlockQueueError::QueueFull),
+            };
+            self.queued_blocks.remove(&highest_key);
+        }
+
+        self.queued_blocks.insert(key, blk);
+        return Ok(());
+    }
+
+    //
+    // a block always has a higher id than its parent, so taking the
+    // lowest id first hands parents to the blockchain before their
+    // children
+    //
+    pub fn return_next_block(&mut self) -> Option<Block> {
+        let lowest_key = *self.queued_blocks.keys().next()?;
+        return self.queued_blocks.remove(&lowest_key);
+    }
+
+    //
+    // every transaction waiting to go into a block, with parents
+    // ahead of the transactions spending their outputs and golden
+    // tickets last, so that adding them back in this order works
+    //
+    pub fn return_pending_transactions(&self) -> Vec<Transaction> {
+        let mut pending_transactions = self.return_transactions();
+        pending_transactions.extend(self.golden_tickets.values().cloned());
+        return pending_transactions;
+    }
+
+    //
+    // the transactions other than golden tickets, in the order they
+    // were let in
+    //
+    pub fn return_transactions(&self) -> Vec<Transaction> {
+        return self.admission_order
+            .values()
+            .map(|tx_hash| self.transactions[tx_hash].tx.clone())
+            .collect();
+    }
+
+    pub fn return_queued_block_count(&self) -> usize {
+        return self.queued_blocks.len();
+    }
+
+    //
+    // only transactions that could go into the next block are let
+    // in, and the first check that fails is returned to the sender
+    //
+    pub fn add_transaction(&mut self, tx: Transaction, shashmap: &Shashmap) -> Result<(), MempoolError> {
+        if self.transactions.contains_key(&tx.return_hash()) {
+            return Err(MempoolError::Duplicate);
+        }
+
+        //
+        // a timestamp far in the future would keep the transaction
+        // from ever expiring
+        //
+        let now = create_timestamp();
+        if tx.return_ts() > now.saturating_add(MAX_TRANSACTION_CLOCK_DRIFT) {
+            return Err(MempoolError::FutureTimestamp { ts: tx.return_ts(), now });
+        }
+
+        let signer = Mempool::validate_structure(&tx)?;
+
+        if !tx.verify_signature(&signer) {
+            return Err(MempoolError::InvalidSignature);
+        }
+
+        if !self.validate_transaction_inputs(&tx, shashmap) {
+            return Err(MempoolError::InputUnavailable);
+        }
+
+        //
+        // golden tickets are paid out of the block reward rather
+        // than their inputs, so they carry no fee
+        //
+        if tx.return_tx_type() != TransactionBroadcastType::GoldenTicket {
+            let fee = tx.return_fees_total();
+            if fee < self.minimum_fee {
+                return Err(MempoolError::FeeTooLow { fee, minimum: self.minimum_fee });
+            }
+        }
+
+        if tx.return_tx_type() == TransactionBroadcastType::GoldenTicket {
+            return self.add_golden_ticket(tx);
+        }
+
+        let routing_work = self.return_routing_work(&tx);
+        let entry = MempoolEntry::new(tx, routing_work);
+        let replaced_hashes = self.return_replaced_transactions(&entry)?;
+        let evicted_hashes = self.return_evicted_transactions_for(&entry, &replaced_hashes)?;
+
+        if replaced_hashes.len() > 0 {
+            println!("replacing {} conflicting transactions", replaced_hashes.len());
+            self.replaced += replaced_hashes.len() as u64;
+            self.evict_transactions(&replaced_hashes);
+        }
+        if evicted_hashes.len() > 0 {
+            println!("mempool full, evicting {} transactions", evicted_hashes.len());
+            self.evicted += evicted_hashes.len() as u64;
+            self.evict_transactions(&evicted_hashes);
+        }
+
+        self.insert_transaction(entry);
+        return Ok(());
+    }
+
+    //
+    // golden tickets are kept apart from other transactions. only a
+    // ticket for the latest block is any use, and we only need one.
+    //
+    fn add_golden_ticket(&mut self, tx: Transaction) -> Result<(), MempoolError> {
+        let target = Mempool::return_golden_ticket_target(&tx).unwrap();
+
+        if Some(target) != self.latest_bsh {
+            return Err(MempoolError::StaleGoldenTicket);
+        }
+        if self.golden_tickets.contains_key(&target) {
+            return Err(MempoolError::GoldenTicketExists);
+        }
+
+        self.work_available += self.return_routing_work(&tx);
+        self.golden_tickets.insert(target, tx);
+
+        return Ok(());
+    }
+
+    fn return_golden_ticket_target(tx: &Transaction) -> Option<[u8; 32]> {
+        return bincode::deserialize::<GoldenTicket>(&tx.body.msg[..]).ok().map(|gt| gt.return_target());
+    }
+
+    //
+    // tickets solving anything but the latest block can no longer
+    // be used and are dropped
+    //
+    fn drop_stale_golden_tickets(&mut self) {
+        let latest_bsh = self.latest_bsh;
+        let stale_targets: Vec<[u8; 32]> = self.golden_tickets
+            .keys()
+            .filter(|target| Some(**target) != latest_bsh)
+            .cloned()
+            .collect();
+
+        for target in stale_targets {
+            let tx = self.golden_tickets.remove(&target).unwrap();
+            self.work_available -= self.return_routing_work(&tx);
+            self.evicted_transactions.push(tx);
+        }
+    }
+
+    //
+    // a transaction spending a slip that a mempool transaction
+    // already spends replaces it only if it pays a strictly higher
+    // fee than the transactions it conflicts with and everything
+    // spending their outputs, all of which are then replaced
+    //
+    fn return_replaced_transactions(&self, entry: &MempoolEntry) -> Result<HashSet<[u8; 32]>, MempoolError> {
+        let mut replaced_hashes: HashSet<[u8; 32]> = entry.tx.body.from
+            .iter()
+            .filter_map(|slip| self.transaction_inputs.get(&slip.return_signature_source()))
+            .cloned()
+            .collect();
+
+        if replaced_hashes.is_empty() { return Ok(replaced_hashes); }
+
+        self.collect_descendants(&mut replaced_hashes);
+
+        let fee = entry.fee;
+        let replaced_fee: u64 = replaced_hashes
+            .iter()
+            .map(|tx_hash| self.transactions[tx_hash].fee)
+            .sum();
+
+        if fee <= replaced_fee {
+            return Err(MempoolError::ReplacementFeeTooLow { fee, replaced_fee });
+        }
+
+        if self.spends_outputs_of(&entry.tx, &replaced_hashes) {
+            return Err(MempoolError::InputUnavailable);
+        }
+
+        return Ok(replaced_hashes);
+    }
+
+    fn spends_outputs_of(&self, tx: &Transaction, tx_hashes: &HashSet<[u8; 32]>) -> bool {
+        return tx.return_from_slips().iter().any(|slip| {
+            self.transaction_outputs.get(&slip.return_signature_source()).map_or(false, |tx_hash| tx_hashes.contains(tx_hash))
+        });
+    }
+
+    //
+    // whether a transaction in the mempool already spends this slip
+    //
+    pub fn is_input_pending(&self, slip: &Slip) -> bool {
+        return self.transaction_inputs.contains_key(&slip.return_signature_source());
+    }
+
+    //
+    // when the mempool is full, find the transactions paying the
+    // lowest fee per byte that have to go for the new one to fit,
+    // along with any transactions spending their outputs. if the new
+    // transaction pays no more than what it would push out, it is
+    // turned away. transactions it replaces have already made room.
+    //
+    fn return_evicted_transactions_for(&self, entry: &MempoolEntry, replaced_hashes: &HashSet<[u8; 32]>) -> Result<HashSet<[u8; 32]>, MempoolError> {
+        if self.transactions.len() < self.max_transactions && self.size + entry.size <= self.max_size {
+            return Ok(HashSet::new());
+        }
+
+        let mut evicted_hashes: HashSet<[u8; 32]> = replaced_hashes.clone();
+        let mut transaction_count = self.transactions.len() - replaced_hashes.len();
+        let mut size = self.size - replaced_hashes.iter().map(|tx_hash| self.transactions[tx_hash].size).sum::<u64>();
+
+        let fee_per_byte = entry.return_fee_per_byte();
+        let mut cheapest = self.eviction_order.iter();
+
+        while transaction_count + 1 > self.max_transactions || size + entry.size > self.max_size {
+            let (lowest_fee_per_byte, _, lowest_hash) = match cheapest.find(|(_, _, tx_hash)| !evicted_hashes.contains(tx_hash)) {
+                Some(lowest) => lowest,
+                None => return Err(MempoolError::MempoolFull),
+            };
+
+            if fee_per_byte <= *lowest_fee_per_byte {
+                return Err(MempoolError::MempoolFull);
+            }
+
+            let mut package: HashSet<[u8; 32]> = HashSet::new();
+            package.insert(*lowest_hash);
+            self.collect_descendants(&mut package);
+
+            for tx_hash in package {
+                if evicted_hashes.insert(tx_hash) {
+                    transaction_count -= 1;
+                    size -= self.transactions[&tx_hash].size;
+                }
+            }
+        }
+
+        //
+        // the new transaction cannot push out a transaction it spends
+        //
+        if self.spends_outputs_of(&entry.tx, &evicted_hashes) {
+            return Err(MempoolError::MempoolFull);
+        }
+
+        return Ok(evicted_hashes.difference(replaced_hashes).cloned().collect());
+    }
+
+    //
+    // drop transactions older than the maximum age, or made before
+    // the earliest block in the genesis period, together with any
+    // transactions spending their outputs
+    //
+    pub fn expire_transactions(&mut self, now: u64, genesis_ts: Option<u64>) -> usize {
+        let max_transaction_age = self.max_transaction_age;
+        let mut expired_hashes: HashSet<[u8; 32]> = self.transactions
+            .values()
+            .filter(|entry| {
+                let ts = entry.tx.return_ts();
+                ts.saturating_add(max_transaction_age) < now || genesis_ts.map_or(false, |genesis_ts| ts < genesis_ts)
+            })
+            .map(|entry| entry.tx_hash)
+            .collect();
+
+        if expired_hashes.is_empty() { return 0; }
+
+        self.collect_descendants(&mut expired_hashes);
+        self.expired += expired_hashes.len() as u64;
+        self.evict_transactions(&expired_hashes);
+
+        return expired_hashes.len();
+    }
+
+    //
+    // add every transaction that spends the output of one already
+    // in the set, following the chain of spends down from each
+    //
+    fn collect_descendants(&self, tx_hashes: &mut HashSet<[u8; 32]>) {
+        let mut unvisited: Vec<[u8; 32]> = tx_hashes.iter().cloned().collect();
+
+        while let Some(tx_hash) = unvisited.pop() {
+            let entry = match self.transactions.get(&tx_hash) {
+                Some(entry) => entry,
+                None => continue,
+            };
+
+            for slip in entry.tx.body.to.iter() {
+                if let Some(child_hash) = self.transaction_inputs.get(&slip.return_signature_source()) {
+                    if tx_hashes.insert(*child_hash) {
+                        unvisited.push(*child_hash);
+                    }
+                }
+            }
+        }
+    }
+
+    //
+    // check the shape of the transaction and return the key that
+    // must have signed it. every input has to belong to the signer.
+    // a golden ticket that spends nothing is signed by the miner it
+    // pays.
+    //
+    fn validate_structure(tx: &Transaction) -> Result<PublicKey, MempoolError> {
+        let from_slips = tx.return_from_slips();
+        let to_slips = tx.return_to_slips();
+
+        if tx.return_tx_type() == TransactionBroadcastType::GoldenTicket {
+            if bincode::deserialize::<GoldenTicket>(&tx.body.msg[..]).is_err() {
+                return Err(MempoolError::Malformed("golden ticket cannot be decoded"));
+            }
+        } else {
+            let input_amt: u64 = from_slips.iter().map(|slip| slip.return_amt()).sum();
+            let output_amt: u64 = to_slips.iter().map(|slip| slip.return_amt()).sum();
+            if output_amt > input_amt {
+                return Err(MempoolError::Malformed("outputs are worth more than inputs"));
+            }
+        }
+
+        let mut slip_ids = HashSet::new();
+        if !from_slips.iter().all(|slip| slip_ids.insert(slip.return_signature_source())) {
+            return Err(MempoolError::Malformed("transaction spends the same slip twice"));
+        }
+
+        let signer = match (from_slips.first(), to_slips.first()) {
+            (Some(slip), _) => slip.return_add(),
+            (None, Some(slip)) if tx.return_tx_type() == TransactionBroadcastType::GoldenTicket => slip.return_add(),
+            _ => return Err(MempoolError::Malformed("transaction has no inputs")),
+        };
+
+        if from_slips.iter().any(|slip| slip.return_add() != signer) {
+            return Err(MempoolError::Malformed("inputs belong to more than one address"));
+        }
+
+        return Ok(signer);
+    }
+
+    //
+    // inputs may be unspent slips on the longest chain or outputs of
+    // transactions waiting in the mempool, which are then bundled
+    // into the same block ahead of the transactions spending them
+    //
+    fn validate_transaction_inputs(&self, tx: &Transaction, shashmap: &Shashmap) -> bool {
+        return tx.return_from_slips()
+            .iter()
+            .all(|slip| self.is_mempool_output(slip) || shashmap.return_value(slip.return_signature_source()) == Some(&-1));
+    }
+
+    fn is_mempool_output(&self, slip: &Slip) -> bool {
+        return self.transaction_outputs.contains_key(&slip.return_signature_source());
+    }
+
+    fn return_routing_work(&self, tx: &Transaction) -> u64 {
+        return tx.return_work_available(&self.publickey);
+    }
+
+    fn insert_transaction(&mut self, mut entry: MempoolEntry) {
+        entry.sequence = self.next_sequence;
+        self.next_sequence += 1;
+
+        self.work_available += entry.routing_work;
+        self.size += entry.size;
+
+        // pending outputs need their identity to be spent
+        entry.tx.set_output_ids();
+
+        let tx_hash = entry.tx_hash;
+        for slip in entry.tx.body.to.iter() {
+            self.transaction_outputs.insert(slip.return_signature_source(), tx_hash);
+        }
+        for slip in entry.tx.body.from.iter() {
+            self.transaction_inputs.insert(slip.return_signature_source(), tx_hash);
+        }
+        self.admission_order.insert(entry.sequence, tx_hash);
+        self.eviction_order.insert(entry.return_eviction_key());
+        self.transactions.insert(tx_hash, entry);
+    }
+
+    //
+    // pick the most valuable transactions that fit in a block of
+    // at most max_size bytes and max_transactions transactions.
+    //
+    // candidates are taken in order of priority. a transaction that
+    // spends the output of another mempool transaction brings that
+    // parent along with it, and the parent is placed first. anything
+    // that does not fit is left where it is.
+    //
+    pub fn select_transactions(&self, max_size: u64, max_transactions: usize) -> Vec<Transaction> {
+        let entries: Vec<&MempoolEntry> = self.admission_order
+            .values()
+            .map(|tx_hash| &self.transactions[tx_hash])
+            .collect();
+
+        let tx_index: HashMap<[u8; 32], usize> = entries
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| (entry.tx_hash, i))
+            .collect();
+
+        let parents: Vec<Vec<usize>> = entries
+            .iter()
+            .map(|entry| entry.tx.body.from
+                .iter()
+                .filter_map(|slip| self.transaction_outputs.get(&slip.return_signature_source()))
+                .filter_map(|parent_hash| tx_index.get(parent_hash).cloned())
+                .collect())
+            .collect();
+
+        let mut candidates: Vec<usize> = (0..entries.len()).collect();
+        candidates.sort_by(|a, b| self.compare_priority(entries[*b], entries[*a]));
+
+        let mut selected = vec![false; entries.len()];
+        let mut selection: Vec<usize> = vec![];
+        let mut block_size: u64 = 0;
+
+        for candidate in candidates {
+            if selected[candidate] { continue; }
+
+            let mut package: Vec<usize> = vec![];
+            let mut visited = selected.clone();
+            Mempool::collect_package(candidate, &parents, &mut visited, &mut package);
+
+            let package_size: u64 = package.iter().map(|i| entries[*i].size).sum();
+            if selection.len() + package.len() > max_transactions || block_size + package_size > max_size {
+                continue;
+            }
+
+            for i in package {
+                selected[i] = true;
+                selection.push(i);
+            }
+            block_size += package_size;
+        }
+
+        return selection.into_iter().map(|i| entries[i].tx.clone()).collect();
+    }
+
+    //
+    // the unselected ancestors of a transaction followed by the
+    // transaction itself, parents before children
+    //
+    fn collect_package(i: usize, parents: &Vec<Vec<usize>>, visited: &mut Vec<bool>, package: &mut Vec<usize>) {
+        if visited[i] { return; }
+        visited[i] = true;
+
+        for parent in parents[i].iter() {
+            Mempool::collect_package(*parent, parents, visited, package);
+        }
+        package.push(i);
+    }
+
+    fn compare_priority(&self, entry_a: &MempoolEntry, entry_b: &MempoolEntry) -> Ordering {
+        return match self.priority {
+            TransactionPriority::FeePerByte => entry_a.return_fee_per_byte().cmp(&entry_b.return_fee_per_byte()),
+            TransactionPriority::RoutingWork => entry_a.routing_work.cmp(&entry_b.routing_work),
         };
     }
 
-    pub fn add_block(&mut self, block: Block) {
-        self.blocks.push(block);
+    //
+    // removed transactions are returned in the order they were let in
+    //
+    fn remove_transactions(&mut self, tx_hashes: &HashSet<[u8; 32]>) -> Vec<Transaction> {
+        let mut removed_entries: Vec<MempoolEntry> = tx_hashes
+            .iter()
+            .filter_map(|tx_hash| self.transactions.remove(tx_hash))
+            .collect();
+        removed_entries.sort_by_key(|entry| entry.sequence);
+
+        for entry in removed_entries.iter() {
+            self.work_available -= entry.routing_work;
+            self.size -= entry.size;
+            self.admission_order.remove(&entry.sequence);
+            self.eviction_order.remove(&entry.return_eviction_key());
+
+            for slip in entry.tx.body.to.iter() {
+                let slip_id = slip.return_signature_source();
+                if self.transaction_outputs.get(&slip_id) == Some(&entry.tx_hash) {
+                    self.transaction_outputs.remove(&slip_id);
+                }
+            }
+            for slip in entry.tx.body.from.iter() {
+                let slip_id = slip.return_signature_source();
+                if self.transaction_inputs.get(&slip_id) == Some(&entry.tx_hash) {
+                    self.transaction_inputs.remove(&slip_id);
+                }
+            }
+        }
+
+        return removed_entries.into_iter().map(|entry| entry.tx).collect();
     }
 
-    pub fn add_transaction(&mut self, tx: Transaction) { 
-        self.work_available = tx.return_work_available("11413212312313321");
-        self.transactions.push(tx.clone());
+    //
+    // transactions that leave without making it into a block are
+    // kept until the node has told their owners
+    //
+    fn evict_transactions(&mut self, tx_hashes: &HashSet<[u8; 32]>) {
+        let mut evicted_transactions = self.remove_transactions(tx_hashes);
+        self.evicted_transactions.append(&mut evicted_transactions);
+    }
+
+    pub fn return_evicted_transactions(&mut self) -> Vec<Transaction> {
+        return std::mem::replace(&mut self.evicted_transactions, vec![]);
+    }
+
+    pub fn return_work_available(&self) -> u64 {
+        return self.work_available;
+    }
+
+    //
+    // transactions caught from blocks that were unwound off the
+    // longest chain are re-checked against the shashmap once the
+    // chain has been rewritten. those whose inputs were spent by
+    // the winning fork are dropped. the rest were already accepted
+    // into a block, so the other admission checks are not repeated,
+    // but they still have to fit under the size and count caps.
+    //
+    // the transactions already waiting are checked again too, as
+    // the unwound blocks may have held the outputs they spend
+    //
+    pub fn recover_transactions(&mut self, shashmap: &Shashmap) {
+        let recovered_transactions = std::mem::replace(&mut self.recovered_transactions, vec![]);
+
+        for tx in recovered_transactions {
+            if self.transactions.contains_key(&tx.return_hash()) { continue; }
+            if tx.return_from_slips().iter().any(|slip| self.is_input_pending(slip)) { continue; }
+            if !self.validate_transaction_inputs(&tx, shashmap) { continue; }
+
+            let routing_work = self.return_routing_work(&tx);
+            let entry = MempoolEntry::new(tx, routing_work);
+
+            match self.return_evicted_transactions_for(&entry, &HashSet::new()) {
+                Ok(evicted_hashes) => {
+                    if evicted_hashes.len() > 0 {
+                        println!("mempool full, evicting {} transactions", evicted_hashes.len());
+                        self.evicted += evicted_hashes.len() as u64;
+                        self.evict_transactions(&evicted_hashes);
+                    }
+                    println!("RECOVERING TRANSACTION FROM PREVIOUS BLOCK");
+                    self.insert_transaction(entry);
+                },
+                Err(_) => {
+                    self.evicted += 1;
+                    self.evicted_transactions.push(entry.tx);
+                },
+            }
+        }
+
+        let mut invalid_hashes: HashSet<[u8; 32]> = self.transactions
+            .values()
+            .filter(|entry| !self.validate_transaction_inputs(&entry.tx, shashmap))
+            .map(|entry| entry.tx_hash)
+            .collect();
+
+        if invalid_hashes.len() > 0 {
+            self.collect_descendants(&mut invalid_hashes);
+            println!("dropping {} transactions whose inputs are no longer on the longest chain", invalid_hashes.len());
+            self.evicted += invalid_hashes.len() as u64;
+            self.evict_transactions(&invalid_hashes);
+        }
     }
 
     pub fn clear_transactions(&mut self) {
-        self.transactions = vec![];
+        self.transactions.clear();
+        self.admission_order.clear();
+        self.eviction_order.clear();
+        self.transaction_outputs.clear();
+        self.transaction_inputs.clear();
+        self.golden_tickets.clear();
         self.work_available = 0;
+        self.size = 0;
+    }
+
+    //
+    // everything pending, golden tickets first as they would be
+    // bundled, and where we stand against the burn fee curve of the
+    // latest block at time now
+    //
+    pub fn return_info(&self, latest_block_header: Option<BlockHeader>, now: u64) -> MempoolInfo {
+        let mut transactions: Vec<MempoolTransactionInfo> = self.golden_tickets
+            .values()
+            .map(|tx| MempoolTransactionInfo {
+                tx_hash:      tx.return_hash(),
+                tx_type:      tx.return_tx_type(),
+                fee:          tx.return_fees_total(),
+                size:         bincode::serialized_size(tx).unwrap(),
+                routing_work: self.return_routing_work(tx),
+            })
+            .collect();
+        transactions.extend(self.admission_order.values().map(|tx_hash| {
+            let entry = &self.transactions[tx_hash];
+            return MempoolTransactionInfo {
+                tx_hash:      entry.tx_hash,
+                tx_type:      entry.tx.return_tx_type(),
+                fee:          entry.fee,
+                size:         entry.size,
+                routing_work: entry.routing_work,
+            };
+        }));
+
+        let (work_needed, time_until_block) = match latest_block_header {
+            Some(block_header) => (
+                BurnFee::return_work_needed(block_header.ts, now, block_header.bf.start),
+                BurnFee::return_time_until_work_needed(block_header.ts, now, block_header.bf.start, self.work_available),
+            ),
+            None => (0, 0),
+        };
+
+        return MempoolInfo {
+            stats:            self.return_stats(),
+            total_fees:       transactions.iter().map(|tx_info| tx_info.fee).sum(),
+            golden_tickets:   self.golden_tickets.len(),
+            queued_blocks:    self.queued_blocks.len(),
+            work_available:   self.work_available,
+            work_needed,
+            time_until_block,
+            transactions,
+        };
     }
 
     //
@@ -60,7 +959,8 @@ impl Mempool {
                     self.work_available,
                     self.transactions.len()
                 );
-                if work_needed <= self.work_available && self.transactions.len() > 0 {
+                let has_transactions = self.transactions.len() > 0 || self.golden_tickets.contains_key(&block_header.bsh);
+                if work_needed <= self.work_available && has_transactions {
                     return true;
                 } else { return false; }
             }
@@ -75,7 +975,27 @@ impl Mempool {
 
         let new_burnfee: BurnFee;
 
-        self.clear_transactions();
+        //
+        // the transactions stay in the mempool until the block makes
+        // it onto the longest chain, so a block that is not accepted
+        // loses nothing.
+        //
+        // the golden ticket solving the previous block goes first
+        //
+        let golden_ticket = previous_block_header
+            .as_ref()
+            .and_then(|previous_block_header| self.golden_tickets.get(&previous_block_header.bsh))
+            .cloned();
+
+        let mut transactions = match golden_ticket {
+            Some(golden_ticket) => {
+                let golden_ticket_size = bincode::serialized_size(&golden_ticket).unwrap();
+                let mut transactions = self.select_transactions(MAX_BLOCK_SIZE - golden_ticket_size, MAX_BLOCK_TRANSACTIONS - 1);
+                transactions.insert(0, golden_ticket);
+                transactions
+            },
+            None => self.select_transactions(MAX_BLOCK_SIZE, MAX_BLOCK_TRANSACTIONS),
+        };
 
         // set the majority of values if we have a previous block header
         match previous_block_header.clone() {
@@ -105,8 +1025,7 @@ impl Mempool {
             }
         }
 
-        // swap transactions with mempool
-        block.set_transactions(&mut self.transactions);
+        block.set_transactions(&mut transactions);
 
         // set burnfee
         block.set_burnfee(new_burnfee);
@@ -138,3 +1057,524 @@ impl Mempool {
     }
 }
 
+impl ChainReorganization for Mempool {
+    fn on_chain_reorganization(&mut self, _block_header: &BlockHeader, blk: &Block, longest_chain: bool) {
+        //
+        // once a block is on the longest chain, whoever bundled it,
+        // the transactions in it are done with. anything else that
+        // spends the same slips can no longer be confirmed and is
+        // evicted, along with whatever spends its outputs.
+        //
+        // the golden tickets we hold follow the tip, which is this
+        // block when it is wound and its parent when it is unwound.
+        //
+        if longest_chain {
+            let block_hashes: HashSet<[u8; 32]> = blk.body.txs.iter().map(|tx| tx.return_hash()).collect();
+
+            let mut conflicting_hashes: HashSet<[u8; 32]> = blk.body.txs
+                .iter()
+                .flat_map(|tx| tx.body.from.iter())
+                .filter_map(|slip| self.transaction_inputs.get(&slip.return_signature_source()))
+                .filter(|tx_hash| !block_hashes.contains(*tx_hash))
+                .cloned()
+                .collect();
+            self.collect_descendants(&mut conflicting_hashes);
+            conflicting_hashes.retain(|tx_hash| !block_hashes.contains(tx_hash));
+
+            self.remove_transactions(&block_hashes);
+            if conflicting_hashes.len() > 0 {
+                println!("evicting {} transactions spending slips spent in block {}", conflicting_hashes.len(), blk.body.id);
+                self.evict_transactions(&conflicting_hashes);
+            }
+
+            let work_available = &mut self.work_available;
+            let publickey = &self.publickey;
+            self.golden_tickets.retain(|_, golden_ticket| {
+                if !block_hashes.contains(&golden_ticket.return_hash()) { return true; }
+                *work_available -= golden_ticket.return_work_available(publickey);
+                return false;
+            });
+
+            self.set_latest_block_hash(Some(blk.return_bsh()));
+            return;
+        }
+
+        self.set_latest_block_hash(Some(blk.body.prevbsh));
+
+        //
+        // golden tickets and rebroadcasts are only valid in the
+        // block that they were created for, so we do not recover them
+        //
+        for tx in blk.body.txs.iter() {
+            match tx.return_tx_type() {
+                TransactionBroadcastType::GoldenTicket | TransactionBroadcastType::Rebroadcast => {},
+                _ => self.recovered_transactions.push(tx.clone()),
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use saito_primitives::crypto::{SecretKey, generate_keys, hash, sign};
+    use saito_primitives::slip::Slip;
+
+    fn create_signed_transaction(privatekey: &SecretKey, input: &Slip, fee: u64) -> Transaction {
+        let mut tx = Transaction::new();
+        tx.add_from_slip(input.clone());
+
+        let mut output = Slip::new(input.return_add());
+        output.set_amt(input.return_amt() - fee);
+        tx.add_to_slip(output);
+
+        let mut signature_hash: [u8; 32] = [0; 32];
+        hash(tx.return_signature_source(), &mut signature_hash);
+        tx.set_sig(sign(&signature_hash, privatekey));
+        tx.set_output_ids();
+
+        return tx;
+    }
+
+    #[test]
+    fn test_add_transaction_admission() {
+        let (privatekey, publickey) = generate_keys();
+        let (other_privatekey, _) = generate_keys();
+
+        let mut input = Slip::new(publickey);
+        input.set_amt(10_000);
+
+        let mut funding_tx = Transaction::new();
+        funding_tx.add_to_slip(input);
+        funding_tx.set_output_ids();
+        let input = funding_tx.return_to_slips()[0].clone();
+
+        let mut shashmap = Shashmap::new();
+        shashmap.insert_new_transaction(&funding_tx);
+
+        let mut mempool = Mempool::new();
+        mempool.set_minimum_fee(100);
+
+        let tx = create_signed_transaction(&privatekey, &input, 500);
+        assert_eq!(mempool.add_transaction(tx.clone(), &shashmap), Ok(()));
+        assert_eq!(mempool.add_transaction(tx, &shashmap), Err(MempoolError::Duplicate));
+
+        let cheap_tx = create_signed_transaction(&privatekey, &input, 50);
+        assert_eq!(mempool.add_transaction(cheap_tx, &shashmap), Err(MempoolError::FeeTooLow { fee: 50, minimum: 100 }));
+
+        let forged_tx = create_signed_transaction(&other_privatekey, &input, 600);
+        assert_eq!(mempool.add_transaction(forged_tx, &shashmap), Err(MempoolError::InvalidSignature));
+
+        let mut unknown_input = Slip::new(publickey);
+        unknown_input.set_amt(20_000);
+        let unknown_input_tx = create_signed_transaction(&privatekey, &unknown_input, 500);
+        assert_eq!(mempool.add_transaction(unknown_input_tx, &shashmap), Err(MempoolError::InputUnavailable));
+
+        let mut no_input_tx = Transaction::new();
+        no_input_tx.add_to_slip(Slip::new(publickey));
+        assert_eq!(mempool.add_transaction(no_input_tx, &shashmap), Err(MempoolError::Malformed("transaction has no inputs")));
+
+        let mut future_tx = Transaction::new();
+        future_tx.set_ts(u64::max_value());
+        match mempool.add_transaction(future_tx, &shashmap) {
+            Err(MempoolError::FutureTimestamp { ts, .. }) => assert_eq!(ts, u64::max_value()),
+            result => panic!("expected a future timestamp rejection, got {:?}", result),
+        }
+
+        assert_eq!(mempool.transactions.len(), 1);
+    }
+
+    #[test]
+    fn test_select_transactions() {
+        let (privatekey, publickey) = generate_keys();
+
+        let mut funding_tx = Transaction::new();
+        for amt in vec![10_000, 20_000] {
+            let mut input = Slip::new(publickey);
+            input.set_amt(amt);
+            funding_tx.add_to_slip(input);
+        }
+        funding_tx.set_output_ids();
+        let inputs = funding_tx.return_to_slips();
+
+        let mut shashmap = Shashmap::new();
+        shashmap.insert_new_transaction(&funding_tx);
+
+        let mut mempool = Mempool::new();
+
+        let parent_tx = create_signed_transaction(&privatekey, &inputs[0], 100);
+        let child_tx = create_signed_transaction(&privatekey, &parent_tx.return_to_slips()[0], 5_000);
+        let other_tx = create_signed_transaction(&privatekey, &inputs[1], 1_000);
+
+        for tx in vec![parent_tx.clone(), child_tx.clone(), other_tx.clone()] {
+            assert_eq!(mempool.add_transaction(tx, &shashmap), Ok(()));
+        }
+
+        // the child pays the most, so it comes in with its parent ahead of it
+        assert_eq!(mempool.select_transactions(MAX_BLOCK_SIZE, 2), vec![parent_tx.clone(), child_tx.clone()]);
+        assert_eq!(mempool.select_transactions(MAX_BLOCK_SIZE, 1), vec![other_tx.clone()]);
+
+        let selection = mempool.select_transactions(MAX_BLOCK_SIZE, MAX_BLOCK_TRANSACTIONS);
+        assert_eq!(selection, vec![parent_tx, child_tx, other_tx.clone()]);
+
+        let work_available = mempool.return_work_available();
+        mempool.remove_transactions(&selection[..2].iter().map(|tx| tx.return_hash()).collect());
+        assert_eq!(mempool.return_transactions(), vec![other_tx.clone()]);
+        assert_eq!(mempool.return_work_available(), work_available - 2 * mempool.return_routing_work(&other_tx));
+    }
+
+    #[test]
+    fn test_bundled_transactions_leave_once_block_is_accepted() {
+        let (privatekey, publickey) = generate_keys();
+
+        let mut input = Slip::new(publickey);
+        input.set_amt(10_000);
+
+        let mut funding_tx = Transaction::new();
+        funding_tx.add_to_slip(input);
+        funding_tx.set_output_ids();
+        let input = funding_tx.return_to_slips()[0].clone();
+
+        let mut shashmap = Shashmap::new();
+        shashmap.insert_new_transaction(&funding_tx);
+
+        let mut mempool = Mempool::new();
+        mempool.set_publickey(&publickey);
+
+        let tx = create_signed_transaction(&privatekey, &input, 500);
+        let routing_work = mempool.return_routing_work(&tx);
+        assert_eq!(mempool.add_transaction(tx, &shashmap), Ok(()));
+        assert_eq!(mempool.return_work_available(), routing_work);
+
+        let wallet = RwLock::new(Wallet::new());
+        let blk = mempool.bundle_block(&wallet, None);
+        assert_eq!(blk.body.txs.len(), 1);
+
+        // a block that has not been accepted leaves the mempool as it was
+        assert_eq!(mempool.transactions.len(), 1);
+        assert_eq!(mempool.return_work_available(), routing_work);
+
+        mempool.on_chain_reorganization(&blk.header(), &blk, true);
+        assert_eq!(mempool.transactions.len(), 0);
+        assert_eq!(mempool.return_work_available(), 0);
+    }
+
+    #[test]
+    fn test_peer_block_confirms_and_conflicts() {
+        let (privatekey, publickey) = generate_keys();
+
+        let mut funding_tx = Transaction::new();
+        for amt in vec![10_000, 20_000] {
+            let mut input = Slip::new(publickey);
+            input.set_amt(amt);
+            funding_tx.add_to_slip(input);
+        }
+        funding_tx.set_output_ids();
+        let inputs = funding_tx.return_to_slips();
+
+        let mut shashmap = Shashmap::new();
+        shashmap.insert_new_transaction(&funding_tx);
+
+        let mut mempool = Mempool::new();
+
+        let confirmed_tx = create_signed_transaction(&privatekey, &inputs[0], 500);
+        let conflicting_tx = create_signed_transaction(&privatekey, &inputs[1], 500);
+        let child_tx = create_signed_transaction(&privatekey, &conflicting_tx.return_to_slips()[0], 500);
+        for tx in vec![confirmed_tx.clone(), conflicting_tx.clone(), child_tx.clone()] {
+            assert_eq!(mempool.add_transaction(tx, &shashmap), Ok(()));
+        }
+
+        // a peer bundles our first transaction and a different spend of the second input
+        let mut blk = Block::new(publickey, [0; 32]);
+        blk.body.id = 1;
+        blk.set_transactions(&mut vec![confirmed_tx, create_signed_transaction(&privatekey, &inputs[1], 600)]);
+
+        mempool.on_chain_reorganization(&blk.header(), &blk, true);
+        assert_eq!(mempool.return_transactions(), vec![]);
+        assert_eq!(mempool.return_evicted_transactions(), vec![conflicting_tx, child_tx]);
+        assert_eq!(mempool.return_work_available(), 0);
+        assert_eq!(mempool.return_stats().size, 0);
+    }
+
+    #[test]
+    fn test_eviction_and_expiry() {
+        let (privatekey, publickey) = generate_keys();
+
+        let mut funding_tx = Transaction::new();
+        for _ in 0..4 {
+            let mut input = Slip::new(publickey);
+            input.set_amt(10_000);
+            funding_tx.add_to_slip(input);
+        }
+        funding_tx.set_output_ids();
+        let inputs = funding_tx.return_to_slips();
+
+        let mut shashmap = Shashmap::new();
+        shashmap.insert_new_transaction(&funding_tx);
+
+        let mut mempool = Mempool::new();
+        mempool.set_limits(DEFAULT_MAX_MEMPOOL_SIZE, 2, DEFAULT_MAX_TRANSACTION_AGE);
+
+        let cheap_tx = create_signed_transaction(&privatekey, &inputs[0], 100);
+        let tx = create_signed_transaction(&privatekey, &inputs[1], 500);
+        assert_eq!(mempool.add_transaction(cheap_tx, &shashmap), Ok(()));
+        assert_eq!(mempool.add_transaction(tx.clone(), &shashmap), Ok(()));
+
+        let cheaper_tx = create_signed_transaction(&privatekey, &inputs[2], 50);
+        assert_eq!(mempool.add_transaction(cheaper_tx, &shashmap), Err(MempoolError::MempoolFull));
+
+        let expensive_tx = create_signed_transaction(&privatekey, &inputs[3], 1_000);
+        assert_eq!(mempool.add_transaction(expensive_tx.clone(), &shashmap), Ok(()));
+        assert_eq!(mempool.return_transactions(), vec![tx, expensive_tx]);
+        assert_eq!(mempool.return_stats().evicted, 1);
+
+        assert_eq!(mempool.expire_transactions(create_timestamp(), None), 0);
+        assert_eq!(mempool.expire_transactions(create_timestamp() + DEFAULT_MAX_TRANSACTION_AGE + 1, None), 2);
+
+        let stats = mempool.return_stats();
+        assert_eq!((stats.transactions, stats.size, stats.expired), (0, 0, 2));
+    }
+
+    #[test]
+    fn test_expiry_at_genesis_boundary() {
+        let (privatekey, publickey) = generate_keys();
+
+        let mut input = Slip::new(publickey);
+        input.set_amt(10_000);
+
+        let mut funding_tx = Transaction::new();
+        funding_tx.add_to_slip(input);
+        funding_tx.set_output_ids();
+        let input = funding_tx.return_to_slips()[0].clone();
+
+        let mut shashmap = Shashmap::new();
+        shashmap.insert_new_transaction(&funding_tx);
+
+        let mut mempool = Mempool::new();
+        let tx = create_signed_transaction(&privatekey, &input, 500);
+        assert_eq!(mempool.add_transaction(tx.clone(), &shashmap), Ok(()));
+
+        // well within the maximum age, but made before the boundary block
+        assert_eq!(mempool.expire_transactions(tx.return_ts() + 1, Some(tx.return_ts())), 0);
+        assert_eq!(mempool.expire_transactions(tx.return_ts() + 1, Some(tx.return_ts() + 1)), 1);
+        assert_eq!(mempool.return_stats().expired, 1);
+    }
+
+    #[test]
+    fn test_recovered_transactions_respect_limits() {
+        let (privatekey, publickey) = generate_keys();
+
+        let mut funding_tx = Transaction::new();
+        for _ in 0..2 {
+            let mut input = Slip::new(publickey);
+            input.set_amt(10_000);
+            funding_tx.add_to_slip(input);
+        }
+        funding_tx.set_output_ids();
+        let inputs = funding_tx.return_to_slips();
+
+        let mut shashmap = Shashmap::new();
+        shashmap.insert_new_transaction(&funding_tx);
+
+        let mut mempool = Mempool::new();
+        mempool.set_limits(DEFAULT_MAX_MEMPOOL_SIZE, 1, DEFAULT_MAX_TRANSACTION_AGE);
+
+        let tx = create_signed_transaction(&privatekey, &inputs[0], 500);
+        assert_eq!(mempool.add_transaction(tx.clone(), &shashmap), Ok(()));
+
+        // a cheaper transaction from an unwound block does not push it out
+        let mut blk = Block::new(publickey, [0; 32]);
+        blk.body.id = 1;
+        blk.set_transactions(&mut vec![create_signed_transaction(&privatekey, &inputs[1], 100)]);
+        let unwound_tx = blk.body.txs[0].clone();
+
+        mempool.on_chain_reorganization(&blk.header(), &blk, false);
+        mempool.recover_transactions(&shashmap);
+
+        assert_eq!(mempool.return_transactions(), vec![tx]);
+        assert_eq!(mempool.return_evicted_transactions(), vec![unwound_tx]);
+    }
+
+    #[test]
+    fn test_recovery_drops_transactions_with_missing_inputs() {
+        let (privatekey, publickey) = generate_keys();
+
+        let mut input = Slip::new(publickey);
+        input.set_amt(10_000);
+
+        let mut funding_tx = Transaction::new();
+        funding_tx.add_to_slip(input);
+        funding_tx.set_output_ids();
+        let input = funding_tx.return_to_slips()[0].clone();
+
+        let mut shashmap = Shashmap::new();
+        shashmap.insert_new_transaction(&funding_tx);
+
+        let mut blk = Block::new(publickey, [0; 32]);
+        blk.body.id = 1;
+        blk.set_transactions(&mut vec![create_signed_transaction(&privatekey, &input, 500)]);
+        shashmap.insert_new_transaction(&blk.body.txs[0]);
+        shashmap.spend_transaction(&blk.body.txs[0], 1);
+
+        let mut mempool = Mempool::new();
+        let child_tx = create_signed_transaction(&privatekey, &blk.body.txs[0].return_to_slips()[0], 500);
+        assert_eq!(mempool.add_transaction(child_tx.clone(), &shashmap), Ok(()));
+
+        // the block is unwound and the winning fork spends its input elsewhere
+        mempool.on_chain_reorganization(&blk.header(), &blk, false);
+        shashmap.unspend_transaction(&blk.body.txs[0]);
+        shashmap.spend_slip(&input, 2);
+
+        mempool.recover_transactions(&shashmap);
+        assert_eq!(mempool.return_transactions(), vec![]);
+        assert_eq!(mempool.return_evicted_transactions(), vec![child_tx]);
+        assert_eq!(mempool.return_stats().evicted, 1);
+    }
+
+    #[test]
+    fn test_replace_by_fee() {
+        let (privatekey, publickey) = generate_keys();
+
+        let mut input = Slip::new(publickey);
+        input.set_amt(10_000);
+
+        let mut funding_tx = Transaction::new();
+        funding_tx.add_to_slip(input);
+        funding_tx.set_output_ids();
+        let input = funding_tx.return_to_slips()[0].clone();
+
+        let mut shashmap = Shashmap::new();
+        shashmap.insert_new_transaction(&funding_tx);
+
+        let mut mempool = Mempool::new();
+
+        let tx = create_signed_transaction(&privatekey, &input, 500);
+        assert_eq!(mempool.add_transaction(tx.clone(), &shashmap), Ok(()));
+        assert!(mempool.is_input_pending(&input));
+
+        let cheaper_tx = create_signed_transaction(&privatekey, &input, 400);
+        assert_eq!(mempool.add_transaction(cheaper_tx, &shashmap), Err(MempoolError::ReplacementFeeTooLow { fee: 400, replaced_fee: 500 }));
+
+        let replacement_tx = create_signed_transaction(&privatekey, &input, 800);
+        assert_eq!(mempool.add_transaction(replacement_tx.clone(), &shashmap), Ok(()));
+
+        assert_eq!(mempool.return_transactions(), vec![replacement_tx]);
+        assert_eq!(mempool.return_evicted_transactions(), vec![tx]);
+        assert_eq!(mempool.return_stats().replaced, 1);
+    }
+
+    fn create_golden_ticket(privatekey: &SecretKey, publickey: PublicKey, target: [u8; 32], random: [u8; 32]) -> Transaction {
+        let mut tx = Transaction::new();
+        tx.set_tx_type(TransactionBroadcastType::GoldenTicket);
+        tx.add_to_slip(Slip::new(publickey));
+        tx.set_msg(bincode::serialize(&GoldenTicket::new(1, target, random, publickey)).unwrap());
+
+        let mut signature_hash: [u8; 32] = [0; 32];
+        hash(tx.return_signature_source(), &mut signature_hash);
+        tx.set_sig(sign(&signature_hash, privatekey));
+
+        return tx;
+    }
+
+    #[test]
+    fn test_golden_ticket_lifecycle() {
+        let (privatekey, publickey) = generate_keys();
+        let shashmap = Shashmap::new();
+
+        let mut blk1 = Block::new(publickey, [0; 32]);
+        blk1.body.id = 1;
+        let mut blk2 = Block::new(publickey, blk1.return_bsh());
+        blk2.body.id = 2;
+
+        let mut mempool = Mempool::new();
+        mempool.set_latest_block_hash(Some(blk1.return_bsh()));
+
+        let stale_golden_ticket = create_golden_ticket(&privatekey, publickey, [1; 32], [0; 32]);
+        assert_eq!(mempool.add_transaction(stale_golden_ticket, &shashmap), Err(MempoolError::StaleGoldenTicket));
+
+        let golden_ticket = create_golden_ticket(&privatekey, publickey, blk1.return_bsh(), [0; 32]);
+        let second_golden_ticket = create_golden_ticket(&privatekey, publickey, blk1.return_bsh(), [1; 32]);
+        assert_eq!(mempool.add_transaction(golden_ticket.clone(), &shashmap), Ok(()));
+        assert_eq!(mempool.add_transaction(second_golden_ticket, &shashmap), Err(MempoolError::GoldenTicketExists));
+
+        let info = mempool.return_info(None, 0);
+        assert_eq!(info.stats.transactions, info.transactions.len());
+        assert_eq!(info.stats.size, info.transactions.iter().map(|tx_info| tx_info.size).sum::<u64>());
+
+        let wallet = RwLock::new(Wallet::new());
+        let blk = mempool.bundle_block(&wallet, Some(blk1.header()));
+        assert_eq!(blk.body.txs.len(), 1);
+        assert_eq!(blk.body.txs[0].return_tx_type(), TransactionBroadcastType::GoldenTicket);
+
+        // someone else's block becomes the tip, so our ticket is no use
+        mempool.on_chain_reorganization(&blk2.header(), &blk2, true);
+        assert_eq!(mempool.return_evicted_transactions(), vec![golden_ticket]);
+        assert_eq!(mempool.return_work_available(), 0);
+    }
+
+    #[test]
+    fn test_block_queue() {
+        let (_, publickey) = generate_keys();
+
+        let mut blk1 = Block::new(publickey, [0; 32]);
+        blk1.body.id = 1;
+        let mut blk2 = Block::new(publickey, blk1.return_bsh());
+        blk2.body.id = 2;
+        let mut blk3 = Block::new(publickey, blk2.return_bsh());
+        blk3.body.id = 3;
+
+        let mut mempool = Mempool::new();
+        mempool.set_max_queued_blocks(2);
+
+        assert_eq!(mempool.add_block(blk3.clone()), Ok(()));
+        assert_eq!(mempool.add_block(blk2.clone()), Ok(()));
+        assert_eq!(mempool.add_block(blk2.clone()), Err(BlockQueueError::Duplicate));
+
+        // the parent pushes out the block furthest from our chain
+        assert_eq!(mempool.add_block(blk1.clone()), Ok(()));
+        assert_eq!(mempool.add_block(blk3.clone()), Err(BlockQueueError::QueueFull));
+
+        assert_eq!(mempool.return_next_block(), Some(blk1));
+        assert_eq!(mempool.return_next_block(), Some(blk2));
+        assert_eq!(mempool.return_next_block(), None);
+    }
+
+    #[test]
+    fn test_mempool_info() {
+        let (privatekey, publickey) = generate_keys();
+
+        let mut input = Slip::new(publickey);
+        input.set_amt(10_000);
+
+        let mut funding_tx = Transaction::new();
+        funding_tx.add_to_slip(input);
+        funding_tx.set_output_ids();
+        let input = funding_tx.return_to_slips()[0].clone();
+
+        let mut shashmap = Shashmap::new();
+        shashmap.insert_new_transaction(&funding_tx);
+
+        let mut mempool = Mempool::new();
+        let tx = create_signed_transaction(&privatekey, &input, 500);
+        assert_eq!(mempool.add_transaction(tx.clone(), &shashmap), Ok(()));
+
+        let mut blk = Block::new(publickey, [0; 32]);
+        blk.set_burnfee(BurnFee::new(10.0, 0));
+        let block_header = blk.header();
+
+        let info = mempool.return_info(Some(block_header.clone()), block_header.ts + 4_000);
+        assert_eq!(info.stats.transactions, 1);
+        assert_eq!(info.stats.size, bincode::serialized_size(&tx).unwrap());
+        assert_eq!(info.total_fees, 500);
+        assert_eq!(info.transactions[0].tx_hash, tx.return_hash());
+
+        // the work needed falls as 1 / elapsed time until it meets what we have
+        assert_eq!(info.work_available, 100_000);
+        assert_eq!(info.work_needed, 250_000);
+        assert_eq!(info.time_until_block, 6_000);
+
+        let info = mempool.return_info(Some(block_header.clone()), block_header.ts + 20_000);
+        assert_eq!(info.time_until_block, 0);
+    }
+}
@@ -1,7 +1,33 @@
-pub struct Runtime {}
+use saito_primitives::block::{Block, BlockHeader};
+
+use crate::blockchain::ChainReorganization;
+
+//
+// the runtime hosts application modules. block data is passed
+// to it on every chain reorganization and it forwards that data
+// to each module so they can keep their own state in sync with
+// the longest chain.
+//
+pub struct Runtime {
+    modules: Vec<Box<dyn ChainReorganization + Send + Sync>>,
+}
 
 impl Runtime {
     pub fn new() -> Runtime {
-        return Runtime{};
+        return Runtime{
+            modules: vec![],
+        };
+    }
+
+    pub fn add_module(&mut self, module: Box<dyn ChainReorganization + Send + Sync>) {
+        self.modules.push(module);
+    }
+}
+
+impl ChainReorganization for Runtime {
+    fn on_chain_reorganization(&mut self, block_header: &BlockHeader, blk: &Block, longest_chain: bool) {
+        for module in self.modules.iter_mut() {
+            module.on_chain_reorganization(block_header, blk, longest_chain);
+        }
     }
 }
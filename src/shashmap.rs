@@ -1,11 +1,18 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use serde::{Serialize, Deserialize};
 use saito_primitives::transaction::Transaction;
 use saito_primitives::slip::Slip;
 
 
-#[derive(Clone)]
+//
+// the slips changed since the shashmap was last saved are tracked
+// so that only those have to be written with the next block
+//
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Shashmap {
     hashmap: HashMap<Vec<u8>, i64>,
+    #[serde(skip)]
+    changes: HashSet<Vec<u8>>,
 }
 
 impl Shashmap {
@@ -13,16 +20,28 @@ impl Shashmap {
     pub fn new() -> Shashmap {
         return Shashmap {
 	    hashmap: HashMap::new() ,
+	    changes: HashSet::new(),
+        }
+    }
+
+    //
+    // a shashmap as it was saved, with nothing left to save
+    //
+    pub fn restore(hashmap: HashMap<Vec<u8>, i64>) -> Shashmap {
+        return Shashmap {
+            hashmap,
+            changes: HashSet::new(),
         }
     }
 
     pub fn insert(&mut self, _x: Vec<u8>, _y: u32) {
+        self.changes.insert(_x.clone());
         self.hashmap.insert(_x, _y as i64);
     }
 
     pub fn insert_new_transaction(&mut self, tx: &Transaction) {
 	for to in tx.return_to_slips().iter() {
-	    self.hashmap.insert(to.return_signature_source(), -1);
+	    self.set_value(to.return_signature_source(), Some(-1));
 	}
     }
 
@@ -39,32 +58,59 @@ impl Shashmap {
 
     pub fn spend_transaction(&mut self, tx: &Transaction, _bid: u32) {
 	for from in tx.return_from_slips().iter() {
-	    self.hashmap.insert(from.return_signature_source(), _bid as i64);
+	    self.set_value(from.return_signature_source(), Some(_bid as i64));
 	}
     }
 
     pub fn unspend_transaction(&mut self, tx: &Transaction) {
 	for from in tx.return_from_slips().iter() {
-	    self.hashmap.insert(from.return_signature_source(), -1);
+	    self.set_value(from.return_signature_source(), Some(-1));
 	}
 
         for to in tx.return_to_slips().iter() {
-            self.hashmap.remove(&to.return_signature_source());
+            self.set_value(to.return_signature_source(), None);
         }
     }
 
     pub fn spend_slip(&mut self, slip: &Slip, _bid: u32) {
-	self.hashmap.insert(slip.return_signature_source(), _bid as i64);
+	self.set_value(slip.return_signature_source(), Some(_bid as i64));
     }
 
     pub fn unspend_slip(&mut self, slip: &Slip, _bid: u32) {
-	self.hashmap.insert(slip.return_signature_source(), -1);
+	self.set_value(slip.return_signature_source(), Some(-1));
     }
 
     pub fn return_value(&self, slip_index: Vec<u8>) -> Option<&i64> {
         return self.hashmap.get(&slip_index);
     }
 
+    pub fn iter(&self) -> impl Iterator<Item = (&Vec<u8>, &i64)> {
+        return self.hashmap.iter();
+    }
+
+    //
+    // the slips changed since the last save, with their value now
+    // or None if they have been removed
+    //
+    pub fn return_changes(&self) -> Vec<(Vec<u8>, Option<i64>)> {
+        return self.changes
+            .iter()
+            .map(|slip_index| (slip_index.clone(), self.hashmap.get(slip_index).cloned()))
+            .collect();
+    }
+
+    pub fn clear_changes(&mut self) {
+        self.changes.clear();
+    }
+
+    fn set_value(&mut self, slip_index: Vec<u8>, value: Option<i64>) {
+        match value {
+            Some(value) => self.hashmap.insert(slip_index.clone(), value),
+            None => self.hashmap.remove(&slip_index),
+        };
+        self.changes.insert(slip_index);
+    }
+
 /***
     pub fn remove(&mut self, _x: String) {
         self.hashmap.remove(&_x);
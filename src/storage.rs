@@ -1,55 +1,588 @@
-use std::fs::{File};
-use std::io::prelude::*;
+use std::{fmt, io};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::path::PathBuf;
 use std::str;
+use std::sync::{Arc, Mutex};
 
 use data_encoding::HEXLOWER;
+use fs2::FileExt;
 
-use saito_primitives::block::{Block, BlockBody};
+use saito_primitives::block::Block;
+use saito_primitives::crypto::PublicKey;
+use saito_primitives::transaction::Transaction;
 
-pub const BLOCKS_DIR: &str = "./data/blocks/";
+use crate::address_index::AddressSlip;
+use crate::block_cache::{BlockCache, BlockCacheStats, DEFAULT_BLOCK_CACHE_SIZE};
+use crate::block_file::BlockFileError;
+use crate::block_store::{BlockStore, BlockStoreBackend, FileBlockStore, MemoryBlockStore, RocksDbBlockStore};
+use crate::blockchain::{Blockchain, BlockNode, ChainTip};
+use crate::shashmap::Shashmap;
+use crate::store::{Store, StoreBatch, create_store, create_memory_store, COL_BLOCK_HEADER, COL_TX_INDEX, COL_SLIPS, COL_METADATA, COL_SHASHMAP};
 
+//
+// the layout of a data directory
+//
+const BLOCKS_DIR: &str = "blocks";
+const INDEX_DIR: &str = "index";
+const WALLET_DIR: &str = "wallet";
+const PEERS_DIR: &str = "peers";
+const LOCK_FILE: &str = "LOCK";
+
+const METADATA_CHAIN_TIP: &[u8] = b"chain_tip";
+const METADATA_MEMPOOL: &[u8] = b"mempool";
+
+//
+// earlier versions saved the whole blockchain under this key
+//
+const METADATA_LEGACY_BLOCKCHAIN: &[u8] = b"blockchain";
+
+//
+// StorageError
+//
+// a block that cannot be found is reported separately from
+// errors reading or decoding the data, so that callers can
+// request the block from peers rather than give up.
+//
+#[derive(Debug)]
+pub enum StorageError {
+    BlockNotFound([u8; 32]),
+    CorruptBlock([u8; 32], BlockFileError),
+    Locked(String),
+    InvalidArchive(String),
+    CorruptChainState(String),
+    Io(io::Error),
+    Serialization(bincode::Error),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StorageError::BlockNotFound(bsh) => write!(f, "block not found: {}", HEXLOWER.encode(bsh)),
+            StorageError::CorruptBlock(bsh, err) => write!(f, "block {} is corrupt: {}", HEXLOWER.encode(bsh), err),
+            StorageError::Locked(path) => write!(f, "data directory is in use by another process: {}", path),
+            StorageError::InvalidArchive(reason) => write!(f, "invalid chain archive: {}", reason),
+            StorageError::CorruptChainState(reason) => write!(f, "saved chain state is corrupt: {}", reason),
+            StorageError::Io(err) => write!(f, "storage io error: {}", err),
+            StorageError::Serialization(err) => write!(f, "storage serialization error: {}", err),
+        }
+    }
+}
+
+impl Error for StorageError {}
+
+impl From<io::Error> for StorageError {
+    fn from(err: io::Error) -> StorageError {
+        return StorageError::Io(err);
+    }
+}
+
+impl From<bincode::Error> for StorageError {
+    fn from(err: bincode::Error) -> StorageError {
+        return StorageError::Serialization(err);
+    }
+}
+
+//
+// the result of checking every block body we hold against its
+// block file header
+//
+#[derive(Debug, Default)]
+pub struct StorageReport {
+    pub verified: usize,
+    pub legacy:   Vec<String>,
+    pub corrupt:  Vec<(String, BlockFileError)>,
+}
+
+//
+// an exclusive advisory lock on the lock file, held for as long as
+// the storage is open. the operating system releases it when the
+// process exits, however it exits, so there is never a stale lock
+// to clean up.
+//
+struct DataDirectoryLock {
+    _lock_file: File,
+}
+
+impl DataDirectoryLock {
+    fn acquire(path: PathBuf) -> Result<DataDirectoryLock, StorageError> {
+        let lock_file = OpenOptions::new().write(true).create(true).open(&path)?;
+        return match lock_file.try_lock_exclusive() {
+            Ok(()) => Ok(DataDirectoryLock { _lock_file: lock_file }),
+            Err(ref err) if err.kind() == fs2::lock_contended_error().kind() => {
+                Err(StorageError::Locked(path.to_string_lossy().into_owned()))
+            },
+            Err(err) => Err(StorageError::Io(err)),
+        };
+    }
+}
+
+//
+// Storage
+//
+// everything lives under a single data directory with a blocks,
+// index, wallet and peers directory, created on first start.
+//
+// full blocks are kept in a block store, in the RocksDB store or
+// as files in the blocks directory. the blockchain index, the
+// shashmap and the transaction and address indexes are kept in
+// the RocksDB store in the index directory.
+//
+// block bodies are written in the block file format and verified
+// whenever they are read.
+//
+// in-memory storage keeps all of this in memory and does not
+// touch the filesystem at all.
+//
+// recently written and read blocks are kept decoded in a block
+// cache shared by every clone of the storage.
+//
+#[derive(Clone)]
 pub struct Storage {
-    pub dest: String,
-    pub blocks_dir: String,
+    pub dest: PathBuf,
+    pub blocks_dir: PathBuf,
+    blocks: Arc<dyn BlockStore>,
+    store: Arc<Store>,
+    block_cache: Arc<Mutex<BlockCache>>,
+    _lock: Option<Arc<DataDirectoryLock>>,
 }
 
 impl Storage {
-    pub fn new() -> Storage {
+    pub fn open(data_dir: &str) -> Result<Storage, StorageError> {
+        return Storage::open_with_backend(data_dir, BlockStoreBackend::RocksDb);
+    }
+
+    pub fn open_with_backend(data_dir: &str, backend: BlockStoreBackend) -> Result<Storage, StorageError> {
+        let dest = PathBuf::from(data_dir);
+        for dir in [BLOCKS_DIR, INDEX_DIR, WALLET_DIR, PEERS_DIR].iter() {
+            fs::create_dir_all(dest.join(dir))?;
+        }
+
+        let lock = DataDirectoryLock::acquire(dest.join(LOCK_FILE))?;
+        let store = create_store(&dest.join(INDEX_DIR).to_string_lossy())?;
+        let blocks_dir = dest.join(BLOCKS_DIR);
+
+        let blocks: Arc<dyn BlockStore> = match backend {
+            BlockStoreBackend::RocksDb => Arc::new(RocksDbBlockStore::new(store.clone(), blocks_dir.clone())),
+            BlockStoreBackend::File => Arc::new(FileBlockStore::new(blocks_dir.clone())),
+        };
+        blocks.remove_incomplete_writes()?;
+
+        return Ok(Storage {
+            blocks_dir,
+            dest,
+            blocks,
+            store,
+            block_cache: Arc::new(Mutex::new(BlockCache::new(DEFAULT_BLOCK_CACHE_SIZE))),
+            _lock: Some(Arc::new(lock)),
+        });
+    }
+
+    pub fn in_memory() -> Storage {
         return Storage {
-            dest: String::from("data"),
-            blocks_dir: String::from("./data/blocks"),
+            dest: PathBuf::new(),
+            blocks_dir: PathBuf::new(),
+            blocks: Arc::new(MemoryBlockStore::new()),
+            store: create_memory_store(),
+            block_cache: Arc::new(Mutex::new(BlockCache::new(DEFAULT_BLOCK_CACHE_SIZE))),
+            _lock: None,
+        };
+    }
+
+    //
+    // in-memory storage in front of the given block store
+    //
+    pub fn with_block_store(blocks: Arc<dyn BlockStore>) -> Storage {
+        return Storage {
+            blocks,
+            ..Storage::in_memory()
+        };
+    }
+
+    pub fn set_block_cache_capacity(&self, capacity: usize) {
+        self.block_cache.lock().unwrap().set_capacity(capacity);
+    }
+
+    pub fn return_block_cache_stats(&self) -> BlockCacheStats {
+        return self.block_cache.lock().unwrap().return_stats();
+    }
+
+    pub fn return_wallet_dir(&self) -> PathBuf {
+        return self.dest.join(WALLET_DIR);
+    }
+
+    pub fn return_peers_dir(&self) -> PathBuf {
+        return self.dest.join(PEERS_DIR);
+    }
+
+    pub fn write_block_to_disk(&self, blk: &Block) -> Result<(), StorageError> {
+        let mut batch = self.store.batch();
+        self.blocks.write_block(blk, &mut batch)?;
+        self.store.commit(batch)?;
+        self.block_cache.lock().unwrap().insert(Arc::new(blk.clone()));
+
+        return Ok(());
+    }
+
+    //
+    // save a block along with what the blockchain keeps for it:
+    // its node in the index, the chain tip and the slips changed
+    // in the shashmap since the last save, all in one batch
+    //
+    pub fn write_block_with_chain_state(
+        &self,
+        blk: &Block,
+        block_node: &BlockNode,
+        chain_tip: &ChainTip,
+        shashmap: &mut Shashmap,
+    ) -> Result<(), StorageError> {
+        let mut batch = self.store.batch();
+        self.blocks.write_block(blk, &mut batch)?;
+        batch.put(COL_BLOCK_HEADER, &block_node.header.bsh, &bincode::serialize(block_node)?);
+        batch.put(COL_METADATA, METADATA_CHAIN_TIP, &bincode::serialize(chain_tip)?);
+        Storage::put_shashmap_changes(&mut batch, shashmap)?;
+        self.store.commit(batch)?;
+
+        shashmap.clear_changes();
+        self.block_cache.lock().unwrap().insert(Arc::new(blk.clone()));
+
+        return Ok(());
+    }
+
+    pub fn read_block_from_disk(&self, bsh: [u8; 32]) -> Result<Arc<Block>, StorageError> {
+        if let Some(blk) = self.block_cache.lock().unwrap().get(&bsh) {
+            return Ok(blk);
+        }
+
+        let blk = Arc::new(self.blocks.read_block(bsh)?);
+
+        self.block_cache.lock().unwrap().insert(blk.clone());
+
+        return Ok(blk);
+    }
+
+    //
+    // transaction index
+    //
+    // maps the hash of every transaction on the longest chain to
+    // the bsh of the block that holds it and its position there.
+    // the hash covers the signed payload only, so it is the same
+    // hash the sender and the mempool knew the transaction by.
+    //
+    pub fn write_transaction_index(&self, blk: &Block) -> Result<(), StorageError> {
+        let bsh = blk.return_bsh();
+
+        let mut batch = self.store.batch();
+        for (position, tx) in blk.body.txs.iter().enumerate() {
+            batch.put(COL_TX_INDEX, &tx.return_hash(), &bincode::serialize(&(bsh, position as u32))?);
         }
+        self.store.commit(batch)?;
+
+        return Ok(());
     }
 
-    pub fn write_block_to_disk(blk: Block) {
-        let mut filename = String::from(BLOCKS_DIR);
- 
-        //filename.push_str(&create_timestamp().to_string());
-        //filename.push_str(&"-");
-        
-        filename.push_str(&blk.return_bsh_as_hex());
-        filename.push_str(&".sai");
+    //
+    // entries that already point at another block are left alone
+    //
+    pub fn remove_transaction_index(&self, blk: &Block) -> Result<(), StorageError> {
+        let bsh = blk.return_bsh();
+
+        let mut batch = self.store.batch();
+        for tx in blk.body.txs.iter() {
+            let tx_hash = tx.return_hash();
+            if let Some((indexed_bsh, _)) = self.read_transaction_index(tx_hash)? {
+                if indexed_bsh == bsh { batch.delete(COL_TX_INDEX, &tx_hash); }
+            }
+        }
+        self.store.commit(batch)?;
+
+        return Ok(());
+    }
+
+    pub fn read_transaction_index(&self, tx_hash: [u8; 32]) -> Result<Option<([u8; 32], u32)>, StorageError> {
+        return match self.store.get(COL_TX_INDEX, &tx_hash)? {
+            Some(encoded) => Ok(Some(bincode::deserialize(&encoded[..])?)),
+            None => Ok(None),
+        };
+    }
+
+    //
+    // address index
+    //
+    // slips are keyed by the public key they were sent to followed
+    // by the slip's signature source, so that all the slips for one
+    // address can be read with a prefix scan
+    //
+    pub fn write_address_slip(&self, address_slip: &AddressSlip) -> Result<(), StorageError> {
+        let key = Storage::return_address_slip_key(&address_slip.slip.return_add(), &address_slip.slip.return_signature_source());
+        self.store.set(COL_SLIPS, &key, &bincode::serialize(address_slip)?)?;
+        return Ok(());
+    }
+
+    pub fn read_address_slip(&self, publickey: &PublicKey, slip_id: &[u8]) -> Result<Option<AddressSlip>, StorageError> {
+        return match self.store.get(COL_SLIPS, &Storage::return_address_slip_key(publickey, slip_id))? {
+            Some(encoded) => Ok(Some(bincode::deserialize(&encoded[..])?)),
+            None => Ok(None),
+        };
+    }
 
-        println!("FILENAME: {}", filename);
+    pub fn remove_address_slip(&self, publickey: &PublicKey, slip_id: &[u8]) -> Result<(), StorageError> {
+        let mut batch = self.store.batch();
+        batch.delete(COL_SLIPS, &Storage::return_address_slip_key(publickey, slip_id));
+        self.store.commit(batch)?;
+        return Ok(());
+    }
+
+    pub fn read_address_slips(&self, publickey: &PublicKey) -> Result<Vec<AddressSlip>, StorageError> {
+        let prefix = publickey.serialize();
+
+        //
+        // the iterator starts at the prefix but runs on to the end of
+        // the column, so stop once we reach the next address
+        //
+        let mut address_slips: Vec<AddressSlip> = vec![];
+        for (_, encoded) in self.store.iter_from_prefix(COL_SLIPS, &prefix[..]).take_while(|(key, _)| key.starts_with(&prefix[..])) {
+            address_slips.push(bincode::deserialize(&encoded[..])?);
+        }
+
+        return Ok(address_slips);
+    }
+
+    fn return_address_slip_key(publickey: &PublicKey, slip_id: &[u8]) -> Vec<u8> {
+        let mut key: Vec<u8> = publickey.serialize().to_vec();
+        key.extend_from_slice(slip_id);
+        return key;
+    }
+
+    //
+    // verify-storage
+    //
+    // check every block in the block store, reporting the ones
+    // that fail to verify
+    //
+    pub fn verify_storage(&self) -> Result<StorageReport, StorageError> {
+        let mut report = StorageReport::default();
+        self.blocks.verify_blocks(&mut report)?;
+        return Ok(report);
+    }
+
+    //
+    // the blockchain is saved a block at a time (see
+    // write_block_with_chain_state). this replaces everything that
+    // was saved with the blockchain and shashmap as they are now,
+    // for when they have changed in other ways, such as when the
+    // chain is truncated on startup.
+    //
+    pub fn write_blockchain_to_disk(&self, blockchain: &Blockchain, shashmap: &mut Shashmap) -> Result<(), StorageError> {
+        let mut batch = self.store.batch();
+
+        for (key, _) in self.store.iter(COL_BLOCK_HEADER) {
+            batch.delete(COL_BLOCK_HEADER, &key[..]);
+        }
+        for block_node in blockchain.return_block_nodes() {
+            batch.put(COL_BLOCK_HEADER, &block_node.header.bsh, &bincode::serialize(block_node)?);
+        }
+        batch.put(COL_METADATA, METADATA_CHAIN_TIP, &bincode::serialize(&blockchain.return_chain_tip())?);
+
+        for (key, _) in self.store.iter(COL_SHASHMAP) {
+            batch.delete(COL_SHASHMAP, &key[..]);
+        }
+        for (slip_index, value) in shashmap.iter() {
+            batch.put(COL_SHASHMAP, slip_index, &bincode::serialize(value)?);
+        }
+
+        self.store.commit(batch)?;
+        shashmap.clear_changes();
+
+        return Ok(());
+    }
+
+    //
+    // returns None if nothing has been saved yet. saved state we
+    // cannot read is an error, and is never replaced by an empty
+    // chain.
+    //
+    pub fn read_blockchain_from_disk(&self) -> Result<Option<(Blockchain, Shashmap)>, StorageError> {
+        let chain_tip: ChainTip = match self.store.get(COL_METADATA, METADATA_CHAIN_TIP)? {
+            Some(chain_tip) => bincode::deserialize(&chain_tip[..])?,
+            None => {
+                if self.store.get(COL_METADATA, METADATA_LEGACY_BLOCKCHAIN)?.is_some() {
+                    return Err(StorageError::CorruptChainState(String::from(
+                        "the blockchain was saved by an earlier version, import the chain from an archive"
+                    )));
+                }
+                return Ok(None);
+            },
+        };
+
+        let mut block_nodes: Vec<BlockNode> = vec![];
+        for (_, encoded) in self.store.iter(COL_BLOCK_HEADER) {
+            block_nodes.push(bincode::deserialize(&encoded[..])?);
+        }
+
+        let mut hashmap: HashMap<Vec<u8>, i64> = HashMap::new();
+        for (slip_index, encoded) in self.store.iter(COL_SHASHMAP) {
+            hashmap.insert(slip_index.to_vec(), bincode::deserialize(&encoded[..])?);
+        }
+
+        let blockchain = Blockchain::restore(chain_tip, block_nodes)
+            .map_err(StorageError::CorruptChainState)?;
+
+        return Ok(Some((blockchain, Shashmap::restore(hashmap))));
+    }
+
+    fn put_shashmap_changes(batch: &mut StoreBatch, shashmap: &Shashmap) -> Result<(), StorageError> {
+        for (slip_index, value) in shashmap.return_changes() {
+            match value {
+                Some(value) => batch.put(COL_SHASHMAP, &slip_index, &bincode::serialize(&value)?),
+                None => batch.delete(COL_SHASHMAP, &slip_index),
+            }
+        }
+        return Ok(());
+    }
+
+    //
+    // the transactions waiting in the mempool, in the order they
+    // should be let back in
+    //
+    pub fn write_mempool_to_disk(&self, txs: &Vec<Transaction>) -> Result<(), StorageError> {
+        let mut batch = self.store.batch();
+        batch.put(COL_METADATA, METADATA_MEMPOOL, &bincode::serialize(txs)?);
+        self.store.commit(batch)?;
+
+        return Ok(());
+    }
+
+    pub fn read_mempool_from_disk(&self) -> Result<Vec<Transaction>, StorageError> {
+        return match self.store.get(COL_METADATA, METADATA_MEMPOOL)? {
+            Some(txs) => Ok(bincode::deserialize(&txs[..])?),
+            None => Ok(vec![]),
+        };
+    }
+}
+
+
+//
+// a file or directory under the system temp directory for a test,
+// removed along with everything in it when the test is done with it
+//
+#[cfg(test)]
+pub struct TempPath {
+    pub path: PathBuf,
+}
+
+#[cfg(test)]
+impl TempPath {
+    pub fn new(name: &str) -> TempPath {
+        let filename = format!("saito-{}-{}-{}", name, std::process::id(), saito_primitives::helper::create_timestamp());
+        return TempPath { path: std::env::temp_dir().join(filename) };
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        if self.path.is_dir() {
+            let _ = fs::remove_dir_all(&self.path);
+        } else {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use saito_primitives::crypto::generate_keys;
+    use saito_primitives::slip::{Slip, SlipSpentStatus};
+
+    #[test]
+    fn test_data_directory_layout_and_lock() {
+        let temp_dir = TempPath::new("test_data_directory");
+        let data_dir = temp_dir.path.to_str().unwrap();
+
+        let storage = Storage::open(data_dir).unwrap();
+        for dir in [BLOCKS_DIR, INDEX_DIR, WALLET_DIR, PEERS_DIR].iter() {
+            assert!(storage.dest.join(dir).is_dir());
+        }
+
+        match Storage::open(data_dir) {
+            Err(StorageError::Locked(_)) => {},
+            _ => panic!("expected the data directory to be locked"),
+        }
+
+        drop(storage);
+        assert!(Storage::open(data_dir).is_ok());
+    }
+
+    #[test]
+    fn test_block_cache() {
+        let (_, publickey) = generate_keys();
+        let storage = Storage::in_memory();
+        storage.set_block_cache_capacity(1);
+
+        let blk1 = Block::new(publickey, [0; 32]);
+        let mut blk2 = Block::new(publickey, blk1.return_bsh());
+        blk2.body.id = 1;
+        let bsh1 = blk1.return_bsh();
+
+        storage.write_block_to_disk(&blk1).unwrap();
+        assert_eq!(storage.read_block_from_disk(bsh1).unwrap().return_bsh(), bsh1);
+        assert_eq!(storage.return_block_cache_stats(), BlockCacheStats { hits: 1, misses: 0, len: 1 });
+
+        // blk1 is evicted and has to be read back from the store
+        storage.write_block_to_disk(&blk2).unwrap();
+        assert_eq!(storage.read_block_from_disk(bsh1).unwrap().return_bsh(), bsh1);
+        assert_eq!(storage.return_block_cache_stats(), BlockCacheStats { hits: 1, misses: 1, len: 1 });
+    }
+
+    #[test]
+    fn test_file_block_store() {
+        let (_, publickey) = generate_keys();
+        let temp_dir = TempPath::new("test_file_block_store");
+        let storage = Storage::open_with_backend(temp_dir.path.to_str().unwrap(), BlockStoreBackend::File).unwrap();
+        storage.set_block_cache_capacity(0);
+
+        let blk = Block::new(publickey, [0; 32]);
+        let bsh = blk.return_bsh();
+
+        storage.write_block_to_disk(&blk).unwrap();
+        assert_eq!(storage.read_block_from_disk(bsh).unwrap().return_bsh(), bsh);
+        assert_eq!(storage.verify_storage().unwrap().verified, 1);
+    }
+
+    #[test]
+    fn test_address_slips_by_key() {
+        let (_, publickey) = generate_keys();
+        let (_, other_publickey) = generate_keys();
+        let storage = Storage::in_memory();
+
+        for (i, key) in [publickey, other_publickey].iter().enumerate() {
+            let mut slip = Slip::new(*key);
+            slip.set_amt(1_000);
+            slip.set_ids(1, i as u32, 0);
+            storage.write_address_slip(&AddressSlip { slip, bid: 1, spent_status: SlipSpentStatus::Unspent }).unwrap();
+        }
 
-        let encode: Vec<u8> = bincode::serialize(blk.return_body()).unwrap();
-        let mut f = File::create(filename).unwrap();
-        f.write_all(&encode[..]).unwrap();
+        // the key that sorts first must not pick up the other's slips
+        let smaller_publickey = if publickey.serialize() < other_publickey.serialize() { publickey } else { other_publickey };
+        let address_slips = storage.read_address_slips(&smaller_publickey).unwrap();
+        assert_eq!(address_slips.len(), 1);
+        assert_eq!(address_slips[0].slip.return_add(), smaller_publickey);
     }
 
-    pub fn read_block_from_disk(bsh: [u8; 32]) -> Block {
-        let mut encoded = Vec::<u8>::new();
-        let mut filename = String::from(BLOCKS_DIR);
- 
-        filename.push_str(&HEXLOWER.encode(&bsh));
-        filename.push_str(&".sai");
+    #[test]
+    fn test_mempool_persistence() {
+        let storage = Storage::in_memory();
+        assert_eq!(storage.read_mempool_from_disk().unwrap(), vec![]);
 
-        println!("ATTEMPTING TO FETCH BLOCK FROM DISK {}", filename);
-        let mut r = File::open(filename).expect("Could not find block at this location");
+        let mut tx = Transaction::new();
+        tx.set_msg(vec![1, 2, 3]);
+        let txs = vec![tx, Transaction::new()];
 
-        r.read_to_end(&mut encoded).unwrap();
-        let body: BlockBody = bincode::deserialize(&encoded[..]).unwrap();
- 
-        return Block::create_from_block_body(body);
+        storage.write_mempool_to_disk(&txs).unwrap();
+        assert_eq!(storage.read_mempool_from_disk().unwrap(), txs);
     }
 }
@@ -1,18 +1,39 @@
-use std::str;
+use std::io;
 use std::sync::Arc;
 pub use kvdb::DBValue;
 use kvdb::{DBTransaction, KeyValueDB};
 use kvdb_rocksdb::{Database, DatabaseConfig};
 
-pub const STORE_PATH: &str = "data/store";
-
 pub const COL_BLOCK_HEADER: Option<u32> = Some(0);
-const NUM_COLS: u32 = 1;
+pub const COL_BLOCK_BODY: Option<u32> = Some(1);
+pub const COL_TX_INDEX: Option<u32> = Some(2);
+pub const COL_SLIPS: Option<u32> = Some(3);
+pub const COL_METADATA: Option<u32> = Some(4);
+pub const COL_SHASHMAP: Option<u32> = Some(5);
+const NUM_COLS: u32 = 6;
 
 pub struct Store {
     storage: Arc<dyn KeyValueDB>,
 }
 
+//
+// a set of writes across any number of columns that is
+// committed to the store atomically
+//
+pub struct StoreBatch {
+    transaction: DBTransaction,
+}
+
+impl StoreBatch {
+    pub fn put(&mut self, column: Option<u32>, key: &[u8], value: &[u8]) {
+        self.transaction.put(column, key, value);
+    }
+
+    pub fn delete(&mut self, column: Option<u32>, key: &[u8]) {
+        self.transaction.delete(column, key);
+    }
+}
+
 impl Store {
     pub fn new(storage: Arc<dyn KeyValueDB>) -> Store {
        Store { storage }
@@ -20,15 +41,37 @@ impl Store {
 
     pub fn get(&self, column: Option<u32>, key: &[u8]) -> Result<Option<Vec<u8>>, io::Error> {
         self.storage.get(column, key).map(|a| a.map(|b| b.to_vec()))
-    } 
-    
-    pub fn set(&mut self, column: Option<u32>, key: &[u8], value: &[u8]) {
-        self.transaction.put(column, key, value)
+    }
+
+    pub fn iter<'a>(&'a self, column: Option<u32>) -> Box<dyn Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a> {
+        self.storage.iter(column)
+    }
+
+    pub fn iter_from_prefix<'a>(&'a self, column: Option<u32>, prefix: &'a [u8]) -> Box<dyn Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a> {
+        self.storage.iter_from_prefix(column, prefix)
+    }
+
+    pub fn batch(&self) -> StoreBatch {
+        StoreBatch { transaction: self.storage.transaction() }
+    }
+
+    pub fn commit(&self, batch: StoreBatch) -> Result<(), io::Error> {
+        self.storage.write(batch.transaction)
+    }
+
+    pub fn set(&self, column: Option<u32>, key: &[u8], value: &[u8]) -> Result<(), io::Error> {
+        let mut batch = self.batch();
+        batch.put(column, key, value);
+        self.commit(batch)
     }
 }
 
-pub fn create_store(path: &str) -> Arc<Store> {
+pub fn create_store(path: &str) -> Result<Arc<Store>, io::Error> {
     let db_config = DatabaseConfig::with_columns(Some(NUM_COLS));
-    let db = Arc::new(Database::open(&db_config, path).expect("Failed to open the database"));
-    Arc::new(Store::new(db))
+    let db = Arc::new(Database::open(&db_config, path)?);
+    Ok(Arc::new(Store::new(db)))
+}
+
+pub fn create_memory_store() -> Arc<Store> {
+    Arc::new(Store::new(Arc::new(kvdb_memorydb::create(NUM_COLS))))
 }
@@ -1,15 +1,23 @@
+use std::sync::Arc;
+
 use saito_primitives::block::Block;
+use saito_primitives::crypto::PublicKey;
 use saito_primitives::transaction::Transaction;
 
+use crate::address_index::AddressInfo;
+use crate::config::ChainConfig;
+use crate::mempool::MempoolInfo;
+use crate::storage::StorageError;
+
 use actix::*;
 
 #[derive(Message)]
 pub struct BlockMessage {
-    pub payload: Block
+    pub payload: Arc<Block>
 }
 
 impl BlockMessage {
-    pub fn new(payload: Block) -> Self {
+    pub fn new(payload: Arc<Block>) -> Self {
         return BlockMessage { payload } 
     }
 }
@@ -24,3 +32,55 @@ impl TransactionMessage {
         return TransactionMessage { payload } 
     }
 }
+
+//
+// asks consensus for a snapshot of the mempool
+//
+pub struct MempoolInfoRequest;
+
+impl Message for MempoolInfoRequest {
+    type Result = MempoolInfo;
+}
+
+//
+// asks consensus what the address index holds for a public key.
+// the answer is None when the node is not indexing addresses.
+//
+pub struct AddressInfoRequest {
+    pub publickey: PublicKey
+}
+
+impl AddressInfoRequest {
+    pub fn new(publickey: PublicKey) -> Self {
+        return AddressInfoRequest { publickey }
+    }
+}
+
+impl Message for AddressInfoRequest {
+    type Result = Option<Result<AddressInfo, StorageError>>;
+}
+
+//
+// inspects or changes the checkpoints and the reorg limit of a
+// running node. every request is answered with the settings in
+// force once it has been applied.
+//
+pub enum ChainConfigRequest {
+    Return,
+    AddCheckpoint(u32, [u8; 32]),
+    RemoveCheckpoint(u32),
+    SetMaxReorgDepth(u32),
+}
+
+impl Message for ChainConfigRequest {
+    type Result = ChainConfig;
+}
+
+//
+// asks consensus to save what it has to and stop the node
+//
+pub struct ShutdownRequest;
+
+impl Message for ShutdownRequest {
+    type Result = ();
+}
@@ -1,10 +1,13 @@
 use serde::{Serialize, Deserialize};
 use std::collections::HashMap;
 
+use saito_primitives::block::{Block, BlockHeader};
 use saito_primitives::slip::{Slip, SlipSpentStatus};
 use saito_primitives::transaction::{Transaction, TransactionBroadcastType};
 use saito_primitives::crypto::{SecretKey, PublicKey, Signature, generate_keys, hash, sign};
 
+use crate::blockchain::ChainReorganization;
+
 
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 pub struct Wallet {
@@ -137,8 +140,57 @@ impl Wallet {
         } 
         return None;
     }
+
+    //
+    // a transaction of ours was dropped from the mempool, so the
+    // inputs we set aside for it can be spent again
+    //
+    pub fn release_inputs(&mut self, slips: &Vec<Slip>) {
+        for slip in slips.iter() {
+            let slip_id = slip.return_signature_source();
+            self.body.slips
+                .iter_mut()
+                .filter(|wallet_slip| wallet_slip.return_signature_source() == slip_id)
+                .for_each(|wallet_slip| wallet_slip.set_spent_status(SlipSpentStatus::Unspent));
+        }
+    }
 }
 
+//
+// keep our slips in sync with the longest chain. when a block is
+// wound we spend our inputs and collect our outputs, and when it
+// is unwound we do the reverse.
+//
+impl ChainReorganization for Wallet {
+    fn on_chain_reorganization(&mut self, _block_header: &BlockHeader, blk: &Block, longest_chain: bool) {
+        let publickey = self.return_publickey();
+
+        for tx in blk.body.txs.iter() {
+            tx.return_from_slips()
+                .iter()
+                .filter(|slip| slip.return_add() == publickey)
+                .for_each(|slip| {
+                    if longest_chain {
+                        self.remove_slip(slip.clone());
+                    } else {
+                        let mut restored_slip = slip.clone();
+                        restored_slip.set_spent_status(SlipSpentStatus::Unspent);
+                        self.add_slip(restored_slip);
+                    }
+                });
+            tx.return_to_slips()
+                .iter()
+                .filter(|slip| slip.return_add() == publickey)
+                .for_each(|slip| {
+                    if longest_chain {
+                        self.add_slip(slip.clone());
+                    } else {
+                        self.remove_slip(slip.clone());
+                    }
+                });
+        }
+    }
+}
 
 
 impl WalletBody {
@@ -159,3 +211,115 @@ impl WalletBody {
 
 
 
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mempool::Mempool;
+    use crate::shashmap::Shashmap;
+
+    #[test]
+    fn test_balance_across_chain_reorganization() {
+        let mut wallet = Wallet::new();
+        let publickey = wallet.return_publickey();
+        let (_, other_publickey) = generate_keys();
+
+        let mut payment_slip = Slip::new(publickey);
+        payment_slip.set_amt(10_000);
+        let mut payment_tx = Transaction::new();
+        payment_tx.add_to_slip(payment_slip);
+
+        let mut blk1 = Block::new(other_publickey, [0; 32]);
+        blk1.body.id = 1;
+        blk1.set_transactions(&mut vec![payment_tx]);
+
+        let mut spend_tx = Transaction::new();
+        spend_tx.add_from_slip(blk1.body.txs[0].return_to_slips()[0].clone());
+        let mut sent_slip = Slip::new(other_publickey);
+        sent_slip.set_amt(4_000);
+        spend_tx.add_to_slip(sent_slip);
+        let mut change_slip = Slip::new(publickey);
+        change_slip.set_amt(6_000);
+        spend_tx.add_to_slip(change_slip);
+
+        let mut blk2 = Block::new(other_publickey, blk1.return_bsh());
+        blk2.body.id = 2;
+        blk2.set_transactions(&mut vec![spend_tx]);
+
+        wallet.on_chain_reorganization(&blk1.header(), &blk1, true);
+        assert_eq!(wallet.return_balance(), 10_000);
+
+        wallet.on_chain_reorganization(&blk2.header(), &blk2, true);
+        assert_eq!(wallet.return_balance(), 6_000);
+        assert_eq!(wallet.body.slips.len(), 1);
+
+        wallet.on_chain_reorganization(&blk2.header(), &blk2, false);
+        assert_eq!(wallet.return_balance(), 10_000);
+        assert_eq!(wallet.body.slips.len(), 1);
+
+        wallet.on_chain_reorganization(&blk1.header(), &blk1, false);
+        assert_eq!(wallet.return_balance(), 0);
+    }
+
+    #[test]
+    fn test_identical_payments_are_counted_and_spendable() {
+        let mut wallet = Wallet::new();
+        let publickey = wallet.return_publickey();
+        let (_, other_publickey) = generate_keys();
+
+        let mut funding_tx = Transaction::new();
+        for _ in 0..2 {
+            let mut funding_slip = Slip::new(other_publickey);
+            funding_slip.set_amt(10_000);
+            funding_tx.add_to_slip(funding_slip);
+        }
+
+        let mut blk1 = Block::new(other_publickey, [0; 32]);
+        blk1.body.id = 1;
+        blk1.set_transactions(&mut vec![funding_tx]);
+
+        // two payments of the same amount to the same key
+        let mut payment_txs: Vec<Transaction> = blk1.body.txs[0].return_to_slips()
+            .into_iter()
+            .map(|funding_slip| {
+                let mut payment_tx = Transaction::new();
+                payment_tx.add_from_slip(funding_slip);
+                let mut payment_slip = Slip::new(publickey);
+                payment_slip.set_amt(10_000);
+                payment_tx.add_to_slip(payment_slip);
+                return payment_tx;
+            })
+            .collect();
+
+        let mut blk2 = Block::new(other_publickey, blk1.return_bsh());
+        blk2.body.id = 2;
+        blk2.set_transactions(&mut payment_txs);
+
+        let mut shashmap = Shashmap::new();
+        for blk in vec![&blk1, &blk2] {
+            for tx in blk.body.txs.iter() {
+                shashmap.insert_new_transaction(tx);
+                shashmap.spend_transaction(tx, blk.body.id);
+            }
+            wallet.on_chain_reorganization(&blk.header(), blk, true);
+        }
+
+        assert_eq!(wallet.return_balance(), 20_000);
+        assert_eq!(wallet.body.slips.len(), 2);
+
+        let mut mempool = Mempool::new();
+        mempool.set_minimum_fee(0);
+
+        for slip in wallet.body.slips.clone() {
+            let mut spend_tx = Transaction::new();
+            spend_tx.add_from_slip(slip);
+            let mut sent_slip = Slip::new(other_publickey);
+            sent_slip.set_amt(10_000);
+            spend_tx.add_to_slip(sent_slip);
+            spend_tx.set_sig(wallet.create_signature(&spend_tx.return_signature_source()));
+
+            assert_eq!(mempool.add_transaction(spend_tx, &shashmap), Ok(()));
+        }
+        assert_eq!(mempool.return_transactions().len(), 2);
+    }
+}